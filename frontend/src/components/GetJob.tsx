@@ -1,4 +1,4 @@
-import React, { useState } from 'react';
+import React, { useEffect, useState } from 'react';
 import jobService from '../services/jobService';
 import './GetJob.css';
 
@@ -34,6 +34,14 @@ const GetJob: React.FC = () => {
     const [error2, setError2] = useState<any>(null);
 
     const [id, setId] = useState<string>('');
+    //follow the shown job while it is being judged
+    const watchedId = responseMessage2 && !['Finished', 'Canceled'].includes(responseMessage2.state) ? responseMessage2.id : null;
+    useEffect(() => {
+        if (watchedId === null) {
+            return;
+        }
+        return jobService.watchJob(watchedId, (job: Job) => setResponseMessage2(job));
+    }, [watchedId]);
     const [query, setQuery] = useState({
         user_id: '',
         user_name: '',
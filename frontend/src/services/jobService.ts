@@ -51,6 +51,17 @@ const getJobById = async (id: number) => {
   }
 };
   
+//follow a job until it is finished, returns a function that stops following
+const watchJob = (id: number, onUpdate: (job: any) => void) => {
+  const source = new EventSource(`${api.defaults.baseURL}/jobs/${id}/events`);
+  source.addEventListener('job', (event) => {
+    onUpdate(JSON.parse((event as MessageEvent).data));
+  });
+  //the server closes the stream once the job is finished, do not reconnect
+  source.onerror = () => source.close();
+  return () => source.close();
+};
+
 const updateJobById = async (id: number) => {
   try {
     const response = await api.put(`/jobs/${id}`);
@@ -112,6 +123,7 @@ export default {
   createJob,
   getJobs,
   getJobById,
+  watchJob,
   updateJobById,
   deleteJobById,
   getContestRank,
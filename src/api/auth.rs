@@ -0,0 +1,334 @@
+use actix_web::{post, web, Error, HttpMessage, HttpResponse};
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use base64::engine::general_purpose::{STANDARD_NO_PAD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::arg::{Auth, Config, SharedConfig};
+use crate::globals::USER_LIST;
+use crate::api::error::HttpError;
+use crate::api::user::{Role, User};
+use crate::storage::storage;
+
+//pbkdf2 rounds for new password hashes
+const PBKDF2_ROUNDS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+const HASH_LEN: usize = 32;
+
+//roles allowed on each route, by method and route pattern
+//an empty list opens the route to everyone, routes not listed here are for admins only
+const ROUTE_ROLES: &[(&str, &str, &[Role])] = &[
+    ("POST", "/login", &[]),
+    ("GET", "/problems", &[]),
+    ("GET", "/problems/{problemid}", &[]),
+    ("GET", "/contests", &[]),
+    ("GET", "/contests/{contestid}", &[]),
+    ("GET", "/contests/{contestid}/ranklist", &[]),
+    ("GET", "/contests/{contestid}/ranklist/ws", &[]),
+    ("GET", "/queue", &[]),
+    ("POST", "/internal/exit", &[Role::Admin]),
+    ("POST", "/internal/reload", &[Role::Admin]),
+    ("POST", "/users", &[Role::Admin]),
+    ("POST", "/contests", &[Role::Admin, Role::ProblemSetter]),
+    ("POST", "/problems", &[Role::Admin, Role::ProblemSetter]),
+    ("PUT", "/problems/{problemid}", &[Role::Admin, Role::ProblemSetter]),
+    ("PUT", "/problems/{problemid}/data", &[Role::Admin, Role::ProblemSetter]),
+    ("DELETE", "/problems/{problemid}", &[Role::Admin, Role::ProblemSetter]),
+    ("PUT", "/jobs/{jobid}", &[Role::Admin, Role::ProblemSetter]),
+    ("DELETE", "/jobs/{jobid}", &[Role::Admin, Role::ProblemSetter]),
+    ("POST", "/jobs/{jobid}/cancel", &[Role::Admin, Role::ProblemSetter]),
+    ("POST", "/jobs/rejudge", &[Role::Admin, Role::ProblemSetter]),
+    ("POST", "/jobs", &[Role::Admin, Role::ProblemSetter, Role::Contestant]),
+    ("GET", "/jobs", &[Role::Admin, Role::ProblemSetter, Role::Contestant]),
+    ("GET", "/jobs/{jobid}", &[Role::Admin, Role::ProblemSetter, Role::Contestant]),
+    ("GET", "/jobs/events", &[Role::Admin, Role::ProblemSetter, Role::Contestant]),
+    ("GET", "/jobs/{jobid}/events", &[Role::Admin, Role::ProblemSetter, Role::Contestant]),
+    ("GET", "/jobs/rejudge/{rejudgeid}", &[Role::Admin, Role::ProblemSetter]),
+    ("GET", "/users", &[Role::Admin, Role::ProblemSetter, Role::Contestant]),
+];
+
+//the user a request was authenticated as, found in the request extensions
+#[derive(Debug, Clone, Copy)]
+pub struct Identity
+{
+    pub user_id: usize,
+    pub role: Role,
+}
+
+//"pbkdf2-sha256$<rounds>$<salt>$<hash>" with a random salt
+pub async fn hash_password(password: &str) -> String
+{
+    let password = password.to_string();
+    tokio::task::spawn_blocking(move || {
+        let mut salt = [0u8; SALT_LEN];
+        getrandom::getrandom(&mut salt).expect("Failed to get random salt");
+        let mut hash = [0u8; HASH_LEN];
+        pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), &salt, PBKDF2_ROUNDS, &mut hash);
+        format!("pbkdf2-sha256${}${}${}", PBKDF2_ROUNDS, STANDARD_NO_PAD.encode(salt), STANDARD_NO_PAD.encode(hash))
+    }).await.unwrap()
+}
+
+async fn verify_password(password: &str, password_hash: &str) -> bool
+{
+    let password = password.to_string();
+    let password_hash = password_hash.to_string();
+    tokio::task::spawn_blocking(move || {
+        let parts: Vec<&str> = password_hash.split('$').collect();
+        let (rounds, salt, expected) = match parts[..]
+        {
+            ["pbkdf2-sha256", rounds, salt, hash] => match (rounds.parse::<u32>(), STANDARD_NO_PAD.decode(salt), STANDARD_NO_PAD.decode(hash))
+            {
+                (Ok(rounds), Ok(salt), Ok(hash)) => (rounds, salt, hash),
+                _ => return false,
+            },
+            _ => return false,
+        };
+        let mut hash = vec![0u8; expected.len()];
+        pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), &salt, rounds, &mut hash);
+        //compare without leaking where they differ
+        !expected.is_empty() && hash.iter().zip(expected.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+    }).await.unwrap_or(false)
+}
+
+//the password hash is signed along, so a new password voids the tokens issued before it
+fn token_mac(auth: &Auth, password_hash: &str, payload: &str) -> Hmac<Sha256>
+{
+    let mut mac = Hmac::<Sha256>::new_from_slice(auth.secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(payload.as_bytes());
+    mac.update(b"$");
+    mac.update(password_hash.as_bytes());
+    mac
+}
+
+//"<user id>.<expiry unix time>.<signature>"
+fn issue_token(auth: &Auth, user_id: usize, password_hash: &str) -> String
+{
+    let payload = format!("{}.{}", user_id, Utc::now().timestamp() as u64 + auth.token_lifetime);
+    let signature = URL_SAFE_NO_PAD.encode(token_mac(auth, password_hash, &payload).finalize().into_bytes());
+    payload + "." + &signature
+}
+
+//the user id of a valid, unexpired token, `password_hash` gives the current hash of a user
+fn check_token(auth: &Auth, token: &str, password_hash: impl FnOnce(usize) -> Option<String>) -> Option<usize>
+{
+    let (payload, signature) = token.rsplit_once('.')?;
+    let (user_id, expiry) = payload.split_once('.')?;
+    let user_id: usize = user_id.parse().ok()?;
+    let password_hash = password_hash(user_id)?;
+    token_mac(auth, &password_hash, payload).verify_slice(&URL_SAFE_NO_PAD.decode(signature).ok()?).ok()?;
+    if expiry.parse::<i64>().ok()? < Utc::now().timestamp()
+    {
+        return None;
+    }
+    Some(user_id)
+}
+
+//give root its configured password if it has none, so there is someone to log in as
+pub async fn init_root_password(config: &Config)
+{
+    let auth = match &config.server.auth
+    {
+        Some(auth) => auth,
+        None => return,
+    };
+    if USER_LIST.lock().await.first().is_none_or(|x| x.password_hash.is_some())
+    {
+        return;
+    }
+    //hash without holding the user list, then check again
+    let password_hash = hash_password(&auth.root_password).await;
+    let mut lock = USER_LIST.lock().await;
+    if let Some(root) = lock.get_mut(0).filter(|x| x.password_hash.is_none())
+    {
+        root.password_hash = Some(password_hash);
+        if storage().update_user(root).is_err()
+        {
+            log::error!("Failed to save the root password");
+        }
+    }
+}
+
+fn auth_error(status: StatusCode, code: u32, reason: &str, message: &str) -> HttpResponse
+{
+    HttpResponse::build(status)
+        .content_type("application/json")
+        .json(HttpError {
+            code,
+            reason: reason.to_string(),
+            message: message.to_string(),
+        })
+}
+
+//middleware checking the bearer token against ROUTE_ROLES, does nothing without server.auth
+pub async fn authorize(req: ServiceRequest, next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse<BoxBody>, Error>
+{
+    let auth = match req.app_data::<web::Data<SharedConfig>>().and_then(|x| x.get().server.auth.clone())
+    {
+        Some(auth) => auth,
+        None => return Ok(next.call(req).await?.map_into_boxed_body()),
+    };
+
+    let token = req.headers().get("Authorization")
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "));
+    let identity = match token
+    {
+        Some(token) =>
+        {
+            let lock = USER_LIST.lock().await;
+            check_token(&auth, token, |user_id| lock.get(user_id).and_then(|x| x.password_hash.clone()))
+                .map(|user_id| Identity { user_id, role: lock[user_id].role })
+        }
+        None => None,
+    };
+    if token.is_some() && identity.is_none()
+    {
+        let response = auth_error(StatusCode::UNAUTHORIZED, 7, "ERR_UNAUTHORIZED", "Invalid or expired token.");
+        return Ok(req.into_response(response));
+    }
+
+    //requests matching no route have nothing to protect and get their 404
+    let roles = req.match_pattern().map(|pattern| {
+        ROUTE_ROLES.iter()
+            .find(|(method, route, _)| *method == req.method().as_str() && *route == pattern)
+            .map_or(&[Role::Admin][..], |(_, _, roles)| *roles)
+    });
+    if let Some(roles) = roles.filter(|x| !x.is_empty())
+    {
+        match identity
+        {
+            None =>
+            {
+                let response = auth_error(StatusCode::UNAUTHORIZED, 7, "ERR_UNAUTHORIZED", "Login required.");
+                return Ok(req.into_response(response));
+            }
+            Some(identity) if !roles.contains(&identity.role) =>
+            {
+                let response = auth_error(StatusCode::FORBIDDEN, 8, "ERR_FORBIDDEN", "Permission denied.");
+                return Ok(req.into_response(response));
+            }
+            Some(_) => {}
+        }
+    }
+
+    if let Some(identity) = identity
+    {
+        req.extensions_mut().insert(identity);
+    }
+    Ok(next.call(req).await?.map_into_boxed_body())
+}
+
+#[derive(Deserialize)]
+pub struct Login
+{
+    name: String,
+    password: String,
+}
+
+#[derive(Serialize)]
+pub struct LoginResponse
+{
+    token: String,
+    user: User,
+}
+
+#[post("/login")]
+pub async fn post_login(login: web::Json<Login>, config: web::Data<SharedConfig>) -> HttpResponse
+{
+    let config = config.get();
+    let auth = match &config.server.auth
+    {
+        Some(auth) => auth,
+        None => return auth_error(StatusCode::NOT_FOUND, 3, "ERR_NOT_FOUND", "Authentication is not enabled."),
+    };
+    let user = USER_LIST.lock().await.iter().find(|x| x.name == login.name).cloned();
+    let user = match user
+    {
+        Some(user) => user,
+        None => return auth_error(StatusCode::UNAUTHORIZED, 7, "ERR_UNAUTHORIZED", "Wrong user name or password."),
+    };
+    let password_hash = match &user.password_hash
+    {
+        Some(password_hash) if verify_password(&login.password, password_hash).await => password_hash.clone(),
+        _ => return auth_error(StatusCode::UNAUTHORIZED, 7, "ERR_UNAUTHORIZED", "Wrong user name or password."),
+    };
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .json(LoginResponse {
+            token: issue_token(auth, user.id, &password_hash),
+            user,
+        })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn auth(secret: &str) -> Auth
+    {
+        Auth {
+            secret: secret.to_string(),
+            token_lifetime: 60,
+            root_password: String::new(),
+        }
+    }
+
+    fn hash(_: usize) -> Option<String>
+    {
+        Some("hash".to_string())
+    }
+
+    fn signed(auth: &Auth, payload: &str) -> String
+    {
+        payload.to_string() + "." + &URL_SAFE_NO_PAD.encode(token_mac(auth, "hash", payload).finalize().into_bytes())
+    }
+
+    #[test]
+    fn issued_tokens_check_out()
+    {
+        let auth = auth("secret");
+        assert_eq!(check_token(&auth, &issue_token(&auth, 3, "hash"), hash), Some(3));
+    }
+
+    #[test]
+    fn tokens_of_another_secret_are_refused()
+    {
+        assert_eq!(check_token(&auth("secret"), &issue_token(&auth("other"), 3, "hash"), hash), None);
+    }
+
+    #[test]
+    fn tokens_from_before_a_password_change_are_refused()
+    {
+        let auth = auth("secret");
+        let token = issue_token(&auth, 3, "hash");
+        assert_eq!(check_token(&auth, &token, |_| Some("new hash".to_string())), None);
+        assert_eq!(check_token(&auth, &token, |_| None), None);
+    }
+
+    #[test]
+    fn changed_tokens_are_refused()
+    {
+        let auth = auth("secret");
+        let token = issue_token(&auth, 3, "hash");
+        let (_, rest) = token.split_once('.').unwrap();
+        assert_eq!(check_token(&auth, &("0.".to_string() + rest), hash), None);
+        assert_eq!(check_token(&auth, "3", hash), None);
+        assert_eq!(check_token(&auth, "", hash), None);
+    }
+
+    #[test]
+    fn expired_tokens_are_refused()
+    {
+        let auth = auth("secret");
+        let expiry = Utc::now().timestamp() - 1;
+        assert_eq!(check_token(&auth, &signed(&auth, &format!("3.{}", expiry)), hash), None);
+        assert_eq!(check_token(&auth, &signed(&auth, &format!("3.{}", expiry + 60)), hash), Some(3));
+    }
+}
@@ -1,15 +1,16 @@
 use std::cmp::Ordering;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use actix_web::{get, post, web, HttpResponse};
 use serde::{Deserialize, Serialize};
 
-use crate::arg::Config;
-use crate::globals::{CONTEST_LIST, JOB_LIST, USER_LIST};
+use crate::arg::Problem;
+use crate::globals::{CONTEST_LIST, PROBLEM_LIST, USER_LIST};
 use crate::api::error::HttpError;
 use crate::api::user::User;
-use crate::sql::{insert_contest, update_contest};
+use crate::storage::{storage, JobFilter, JobPage, Result};
 
-use super::job::Job;
+use super::job::{dynamic_ranking_ratio, get_packs, Job, JobCase};
+use super::state::{JobResult, JobState};
 
 //used to respond
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -109,6 +110,68 @@ pub fn rank(scoring_rule: &str, x: &FullUserInfo) -> FullUserInfo
     a
 }
 
+//add the relative performance part to the scores of dynamic ranking problems
+//each accepted case gets score * ratio * (fastest accepted cpu time / its cpu time)
+pub fn dynamic_rank(problems: &[Problem], problem_ids: &[usize], full_rank: &mut [FullUserInfo])
+{
+    for (index, problem_id) in problem_ids.iter().enumerate()
+    {
+        let problem = match problems.iter().find(|x| x.id == *problem_id)
+        {
+            Some(problem) => problem,
+            None => continue,
+        };
+        let ratio = dynamic_ranking_ratio(problem);
+        if ratio == 0.0
+        {
+            continue;
+        }
+
+        //fastest accepted time of each case among all competitors, on the wall clock like the time of a case
+        let packs = get_packs(problem);
+        let mut min_times: Vec<Option<u64>> = vec![None; problem.cases.len() + 1];
+        for user in full_rank.iter()
+        {
+            for job in user.problems[index].iter().filter(|x| x.state == JobState::Finished)
+            {
+                for case in passed_cases(job, &packs)
+                {
+                    if let Some(min_time) = min_times.get_mut(case.id)
+                    {
+                        *min_time = Some(min_time.map_or(case.time, |x| x.min(case.time)));
+                    }
+                }
+            }
+        }
+
+        for user in full_rank.iter_mut()
+        {
+            for job in user.problems[index].iter_mut().filter(|x| x.state == JobState::Finished)
+            {
+                let mut bonus = 0.0;
+                for case in passed_cases(job, &packs)
+                {
+                    if let (Some(Some(min_time)), Some(problem_case)) = (min_times.get(case.id), problem.cases.get(case.id - 1))
+                    {
+                        let performance = if case.time == 0 { 1.0 } else { *min_time as f64 / case.time as f64 };
+                        bonus += problem_case.score * ratio * performance;
+                    }
+                }
+                job.score += bonus;
+            }
+        }
+    }
+}
+
+//accepted cases of a job whose whole pack was accepted, only those earn a bonus
+fn passed_cases<'a>(job: &'a Job, packs: &[Vec<usize>]) -> Vec<&'a JobCase>
+{
+    packs.iter()
+        .filter(|pack| pack.iter().all(|x| job.cases.get(*x).is_some_and(|x| x.result == JobResult::Accepted)))
+        .flat_map(|pack| pack.iter().filter_map(|x| job.cases.get(*x)))
+        .collect()
+}
+
 //compare function
 pub fn cmp_rank(tie_breaker: &str, a: &FullUserInfo, b: &FullUserInfo) -> Ordering
 {
@@ -127,110 +190,116 @@ pub fn cmp_rank(tie_breaker: &str, a: &FullUserInfo, b: &FullUserInfo) -> Orderi
         "submission_time" =>
         {
             let mut a_time = a.times.iter().max().unwrap().clone();
-            if a_time == ""
+            if a_time.is_empty()
             {
                 a_time = "9".to_string();
             }
             let mut b_time = b.times.iter().max().unwrap().clone();
-            if b_time == ""
+            if b_time.is_empty()
             {
                 b_time = "9".to_string();
             }
-            return a_time.cmp(&b_time);
+            a_time.cmp(&b_time)
         }
         "submission_count" =>
         {
-            return a.count.cmp(&b.count);
+            a.count.cmp(&b.count)
         }
         "user_id" =>
         {
-            return a.user_id.cmp(&b.user_id);
+            a.user_id.cmp(&b.user_id)
         }
         _ =>
         {
-            return Ordering::Equal;
+            Ordering::Equal
         }
     }
 }
 
 //get ranklist
 #[get("/contests/{contestid}/ranklist")]
-pub async fn get_contests_ranklist(get_contest: web::Path<usize>, query: web::Query<RankQuery>, config: web::Data<Config>) -> HttpResponse
+pub async fn get_contests_ranklist(get_contest: web::Path<usize>, query: web::Query<RankQuery>) -> HttpResponse
+{
+    match build_ranklist(*get_contest, &query).await
+    {
+        Ok(Some(ranklist)) => HttpResponse::Ok()
+            .content_type("application/json")
+            .json(ranklist),
+        Ok(None) => HttpResponse::NotFound()
+            .content_type("application/json")
+            .json(HttpError {
+                code: 3,
+                reason: "ERR_NOT_FOUND".to_string(),
+                message: "Contest ".to_string() + &get_contest.to_string() + " not found.",
+            }),
+        Err(_) => ranklist_error(),
+    }
+}
+
+//the jobs of a ranklist could not be read
+pub fn ranklist_error() -> HttpResponse
+{
+    HttpResponse::InternalServerError()
+        .content_type("application/json")
+        .json(HttpError {
+            code: 5,
+            reason: "ERR_EXTERNAL".to_string(),
+            message: "SQL error".to_string(),
+        })
+}
+
+//ranklist of contest `id`, 0 for all users and problems, None if there is no such contest
+pub async fn build_ranklist(id: usize, query: &RankQuery) -> Result<Option<Vec<UserRank>>>
 {
     let lock = USER_LIST.lock().await;
     let user_list = lock.clone();
     drop(lock);
-    let lock = JOB_LIST.lock().await;
-    let job_list = lock.clone();
-    drop(lock);
+    let problem_list = PROBLEM_LIST.lock().await.clone();
 
-    let id = *get_contest;
-    let user_count = user_list.len();
-    let mut full_rank: Vec<FullUserInfo> = Vec::new();
-    //whole rank
-    if id == 0
+    //whole rank, or spec contest rank
+    let (user_ids, problem_ids): (Vec<usize>, Vec<usize>) = if id == 0
     {
-        for user_id in 0..user_count
-        {
-            let mut user = FullUserInfo {
-                user_id,
-                problems: Vec::new(),
-                scores: Vec::new(),
-                times: Vec::new(),
-                count: 0,
-            };
-            for problem in config.problems.iter()
-            {
-                let jobs: Vec<Job> = job_list.iter()
-                    .filter(|x| x.submission.problem_id == problem.id && x.submission.user_id == user_id)
-                    .cloned()
-                    .collect();
-                user.problems.push(jobs);
-            }
-            full_rank.push(user);
-        }
+        ((0..user_list.len()).collect(), problem_list.iter().map(|x| x.id).collect())
     }
-    //spec contest rank
     else
     {
         match CONTEST_LIST.lock().await.get(id - 1)
         {
-            Some(contest) =>
-            {
-                for user_id in contest.user_ids.iter()
-                {
-                    let mut user = FullUserInfo {
-                        user_id: *user_id,
-                        problems: Vec::new(),
-                        scores: Vec::new(),
-                        times: Vec::new(),
-                        count: 0,
-                    };
-                    //find all submission of a user
-                    for problem_id in contest.problem_ids.iter()
-                    {
-                        let jobs: Vec<Job> = job_list.iter()
-                            .filter(|x| x.submission.problem_id == *problem_id && x.submission.user_id == *user_id)
-                            .cloned()
-                            .collect();
-                        user.problems.push(jobs);
-                    }
-                    full_rank.push(user);
-                }
-            }
-            None =>
-            {
-                return HttpResponse::NotFound()
-                    .content_type("application/json")
-                    .json(HttpError {
-                        code: 3,
-                        reason: "ERR_NOT_FOUND".to_string(),
-                        message: "Contest ".to_string() + &id.to_string() + " not found.",
-                    });
-            }
+            Some(contest) => (contest.user_ids.clone(), contest.problem_ids.clone()),
+            None => return Ok(None),
         }
+    };
+
+    //only the jobs that count, without their source code
+    //the whole rank has all users, which need no condition
+    let filter = JobFilter {
+        user_ids: if id == 0 { None } else { Some(user_ids.clone()) },
+        problem_ids: Some(problem_ids.clone()),
+        ..Default::default()
+    };
+    let page = JobPage { skip_source: true, ..Default::default() };
+    let job_list = storage().query_jobs(&filter, &page).inspect_err(|err| log::error!("Failed to read jobs: {}", err))?;
+    //all submissions of a user to a problem, in order
+    let mut submissions: HashMap<(usize, usize), Vec<Job>> = HashMap::new();
+    for job in job_list
+    {
+        submissions.entry((job.submission.user_id, job.submission.problem_id)).or_default().push(job);
     }
 
+    let mut full_rank: Vec<FullUserInfo> = user_ids.iter()
+        .map(|user_id| FullUserInfo {
+            user_id: *user_id,
+            problems: problem_ids.iter()
+                .map(|problem_id| submissions.remove(&(*user_id, *problem_id)).unwrap_or_default())
+                .collect(),
+            scores: Vec::new(),
+            times: Vec::new(),
+            count: 0,
+        })
+        .collect();
+
+    dynamic_rank(&problem_list, &problem_ids, &mut full_rank);
+
     let mut scoring_rule = "last".to_string();
     if let Some(rule) = &query.scoring_rule
     {
@@ -249,7 +318,6 @@ pub async fn get_contests_ranklist(get_contest: web::Path<usize>, query: web::Qu
 
     //trim the rank result and deal with same-rank situation
     let mut ranklist: Vec<UserRank> = Vec::new();
-    let mut count: usize = 1;
     let mut rank: usize = 1;
     let mut last: FullUserInfo = FullUserInfo {
         user_id: 0,
@@ -258,11 +326,11 @@ pub async fn get_contests_ranklist(get_contest: web::Path<usize>, query: web::Qu
         times: Vec::new(),
         count: 0,
     };
-    for user in after_rank.iter()
+    for (count, user) in after_rank.iter().enumerate()
     {
         if cmp_rank(&tie_breaker, &last, user) != Ordering::Equal
         {
-            rank = count;
+            rank = count + 1;
         }
         ranklist.push(UserRank {
             user: user_list[user.user_id].clone(),
@@ -270,16 +338,13 @@ pub async fn get_contests_ranklist(get_contest: web::Path<usize>, query: web::Qu
             scores: user.scores.clone(),
         });
         last = user.clone();
-        count += 1;
     }
 
-    HttpResponse::Ok()
-        .content_type("application/json")
-        .json(ranklist)
+    Ok(Some(ranklist))
 }
 
 #[post("/contests")]
-pub async fn post_contests(post_contest: web::Json<PostContest>, config: web::Data<Config>) -> HttpResponse
+pub async fn post_contests(post_contest: web::Json<PostContest>) -> HttpResponse
 {
     if post_contest.from >= post_contest.to
     {
@@ -321,7 +386,7 @@ pub async fn post_contests(post_contest: web::Json<PostContest>, config: web::Da
         }
     }
     //check for repeated problem
-    let problem_amount = config.problems.len();
+    let problem_list = PROBLEM_LIST.lock().await.clone();
     let mut problem_set: HashSet<usize> = HashSet::new();
     for problem_id in post_contest.problem_ids.iter()
     {
@@ -335,7 +400,7 @@ pub async fn post_contests(post_contest: web::Json<PostContest>, config: web::Da
                     message: "Invalid argument problem.".to_string(),
                 });
         }
-        else if *problem_id >= problem_amount
+        else if !problem_list.iter().any(|x| x.id == *problem_id)
         {
             return HttpResponse::NotFound()
                 .content_type("application/json")
@@ -380,9 +445,9 @@ pub async fn post_contests(post_contest: web::Json<PostContest>, config: web::Da
                 to: post_contest.to.clone(),
                 problem_ids: post_contest.problem_ids.clone(),
                 user_ids: post_contest.user_ids.clone(),
-                submission_limit: post_contest.submission_limit.clone(),
+                submission_limit: post_contest.submission_limit,
             };
-            if let Err(_) = update_contest(&lock[id - 1]).await
+            if storage().update_contest(&lock[id - 1]).is_err()
             {
                 return HttpResponse::InternalServerError()
                         .content_type("application/json")
@@ -405,10 +470,10 @@ pub async fn post_contests(post_contest: web::Json<PostContest>, config: web::Da
         to: post_contest.to.clone(),
         problem_ids: post_contest.problem_ids.clone(),
         user_ids: post_contest.user_ids.clone(),
-        submission_limit: post_contest.submission_limit.clone(),
+        submission_limit: post_contest.submission_limit,
     };
     lock.push(contest.clone());
-    if let Err(_) = insert_contest(&contest).await
+    if storage().insert_contest(&contest).is_err()
     {
         return HttpResponse::InternalServerError()
                 .content_type("application/json")
@@ -440,13 +505,13 @@ pub async fn get_contests_id(get_contest: web::Path<usize>) -> HttpResponse
     {
         Some(contest) =>
         {
-            return HttpResponse::Ok()
+            HttpResponse::Ok()
                 .content_type("application/json")
-                .json(contest);
+                .json(contest)
         }
         None =>
         {
-            return HttpResponse::NotFound()
+            HttpResponse::NotFound()
                 .content_type("application/json")
                 .json(HttpError {
                     code: 3,
@@ -463,4 +528,79 @@ pub async fn get_contests() -> HttpResponse
     HttpResponse::Ok()
         .content_type("application/json")
         .json(CONTEST_LIST.lock().await.clone())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::api::job::PostJob;
+
+    fn job_with(results: &[JobResult]) -> Job
+    {
+        let post_job = PostJob {
+            source_code: String::new(),
+            language: "Rust".to_string(),
+            user_id: 0,
+            contest_id: 0,
+            problem_id: 0,
+        };
+        let mut job = Job::new(0, post_job, results.len());
+        for (case, result) in job.cases.iter_mut().skip(1).zip(results)
+        {
+            case.result = *result;
+        }
+        job
+    }
+
+    #[test]
+    fn failed_packs_earn_no_bonus()
+    {
+        let packs = vec![vec![1, 2], vec![3]];
+        let job = job_with(&[JobResult::Accepted, JobResult::WrongAnswer, JobResult::Accepted]);
+        let passed: Vec<usize> = passed_cases(&job, &packs).iter().map(|x| x.id).collect();
+        assert_eq!(passed, vec![3]);
+    }
+
+    #[test]
+    fn passed_packs_earn_a_bonus_for_every_case()
+    {
+        let packs = vec![vec![1, 2], vec![3]];
+        let job = job_with(&[JobResult::Accepted, JobResult::Accepted, JobResult::Skipped]);
+        let passed: Vec<usize> = passed_cases(&job, &packs).iter().map(|x| x.id).collect();
+        assert_eq!(passed, vec![1, 2]);
+    }
+
+    #[test]
+    fn dynamic_bonus_follows_time()
+    {
+        let problem: Problem = serde_json::from_value(serde_json::json!({
+            "id": 0,
+            "name": "aplusb",
+            "type": "dynamic_ranking",
+            "misc": {"dynamic_ranking_ratio": 0.5},
+            "cases": [{"score": 100.0, "input_file": "", "answer_file": "", "time_limit": 0, "memory_limit": 0}]
+        })).unwrap();
+        //the first job uses less cpu time but takes twice as long on the wall clock
+        let mut full_rank: Vec<FullUserInfo> = [(40, 10), (20, 20)].iter()
+            .enumerate()
+            .map(|(user_id, (time, cpu_time))|
+            {
+                let mut job = job_with(&[JobResult::Accepted]);
+                job.state = JobState::Finished;
+                job.cases[1].time = *time;
+                job.cases[1].cpu_time = *cpu_time;
+                FullUserInfo {
+                    user_id,
+                    problems: vec![vec![job]],
+                    scores: Vec::new(),
+                    times: Vec::new(),
+                    count: 0,
+                }
+            })
+            .collect();
+        dynamic_rank(&[problem], &[0], &mut full_rank);
+        assert_eq!(full_rank[0].problems[0][0].score, 25.0);
+        assert_eq!(full_rank[1].problems[0][0].score, 50.0);
+    }
+}
@@ -0,0 +1,191 @@
+use actix_web::{get, web, HttpResponse};
+use actix_web::web::Bytes;
+use futures_util::stream;
+use serde::Deserialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time;
+
+use crate::globals::{JOB_CACHE, JOB_EVENTS};
+use crate::api::error::HttpError;
+use crate::api::job::{job_json, Job};
+use crate::api::state::JobState;
+
+//seconds between comments that keep idle streams open through proxies
+const KEEP_ALIVE: u64 = 15;
+
+//tell the streams about a new snapshot of a job
+pub fn publish_job(job: &Job)
+{
+    //no receivers is not an error
+    let _ = JOB_EVENTS.send(job.clone());
+}
+
+#[derive(Deserialize)]
+pub struct EventQuery
+{
+    user_id: Option<usize>,
+    contest_id: Option<usize>,
+    problem_id: Option<usize>,
+}
+
+impl EventQuery
+{
+    fn matches(&self, job: &Job) -> bool
+    {
+        self.user_id.is_none_or(|x| x == job.submission.user_id)
+            && self.contest_id.is_none_or(|x| x == job.submission.contest_id)
+            && self.problem_id.is_none_or(|x| x == job.submission.problem_id)
+    }
+}
+
+//a job as GET /jobs/{jobid} shows it, without the source code
+fn job_event(job: &Job) -> Bytes
+{
+    Bytes::from(format!("event: job\ndata: {}\n\n", job_json(job, false)))
+}
+
+fn event_stream(body: impl stream::Stream<Item = Result<Bytes, actix_web::Error>> + 'static) -> HttpResponse
+{
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(body)
+}
+
+enum Update
+{
+    Job(Job),
+    //nothing happened for KEEP_ALIVE seconds
+    Idle,
+    //the stream fell behind and some snapshots were dropped
+    Missed,
+}
+
+//wait for the next job snapshot, None when the server is going away
+async fn next_update(rx: &mut broadcast::Receiver<Job>) -> Option<Update>
+{
+    match time::timeout(time::Duration::from_secs(KEEP_ALIVE), rx.recv()).await
+    {
+        Ok(Ok(job)) => Some(Update::Job(job)),
+        Ok(Err(RecvError::Lagged(count))) =>
+        {
+            log::warn!("Event stream missed {} job updates", count);
+            Some(Update::Missed)
+        }
+        Ok(Err(RecvError::Closed)) => None,
+        Err(_) => Some(Update::Idle),
+    }
+}
+
+fn keep_alive() -> Bytes
+{
+    Bytes::from_static(b": keep-alive\n\n")
+}
+
+//every job update, optionally only those of one user, contest or problem
+#[get("/jobs/events")]
+pub async fn get_jobs_events(query: web::Query<EventQuery>) -> HttpResponse
+{
+    let rx = JOB_EVENTS.subscribe();
+    let query = query.into_inner();
+    event_stream(stream::unfold((rx, query), |(mut rx, query)| async move {
+        loop
+        {
+            match next_update(&mut rx).await?
+            {
+                Update::Job(job) if query.matches(&job) => return Some((Ok(job_event(&job)), (rx, query))),
+                Update::Job(_) | Update::Missed => continue,
+                Update::Idle => return Some((Ok(keep_alive()), (rx, query))),
+            }
+        }
+    }))
+}
+
+//updates of one job, starting with its current snapshot and ending once it is finished
+#[get("/jobs/{jobid}/events")]
+pub async fn get_jobs_id_events(job_id: web::Path<usize>) -> HttpResponse
+{
+    let job_id = job_id.into_inner();
+    //subscribe before reading the job so no update falls in between
+    let rx = JOB_EVENTS.subscribe();
+    let job = match current_job(job_id).await
+    {
+        Some(job) => job,
+        None =>
+        {
+            return HttpResponse::NotFound()
+                .content_type("application/json")
+                .json(HttpError {
+                    code: 3,
+                    reason: "ERR_NOT_FOUND".to_string(),
+                    message: "Job ".to_string() + &job_id.to_string() + " not found."
+                });
+        }
+    };
+
+    event_stream(stream::unfold((rx, Some(job), false), move |(mut rx, first, done)| async move {
+        if let Some(job) = first
+        {
+            let done = is_done(&job);
+            return Some((Ok(job_event(&job)), (rx, None, done)));
+        }
+        if done
+        {
+            return None;
+        }
+        loop
+        {
+            let job = match next_update(&mut rx).await?
+            {
+                Update::Job(job) if job.id == job_id => job,
+                Update::Job(_) => continue,
+                //a deleted job never finishes
+                Update::Idle if current_job(job_id).await.is_none() => return None,
+                Update::Idle => return Some((Ok(keep_alive()), (rx, None, false))),
+                //missed updates may include this job, send it again from the storage
+                Update::Missed => current_job(job_id).await?,
+            };
+            let done = is_done(&job);
+            return Some((Ok(job_event(&job)), (rx, None, done)));
+        }
+    }))
+}
+
+//None for deleted jobs, and when the storage fails
+async fn current_job(job_id: usize) -> Option<Job>
+{
+    JOB_CACHE.lock().await.get(job_id).ok().flatten()
+}
+
+//a single job stream ends with the job
+fn is_done(job: &Job) -> bool
+{
+    job.state == JobState::Finished || job.state == JobState::Canceled
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::api::job::PostJob;
+
+    #[test]
+    fn events_are_jobs_without_source_code()
+    {
+        let post_job = PostJob {
+            source_code: "fn main() {}".to_string(),
+            language: "Rust".to_string(),
+            user_id: 1,
+            contest_id: 2,
+            problem_id: 3,
+        };
+        let job = Job::new(4, post_job, 2);
+        let mut expected = serde_json::to_value(&job).unwrap();
+        expected["submission"].as_object_mut().unwrap().remove("source_code");
+        let event = job_event(&job);
+        let data = std::str::from_utf8(&event).unwrap()
+            .strip_prefix("event: job\ndata: ").unwrap()
+            .strip_suffix("\n\n").unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(data).unwrap(), expected);
+    }
+}
@@ -1,19 +1,30 @@
 use actix_web::{delete, put, HttpResponse};
 use actix_web::{get, post, web};
 use serde::{Deserialize, Serialize};
-use serde_json::Map;
 use tokio::sync::mpsc;
 use tokio::time;
 use tokio::process::Command;
-use std::process::ExitStatus;
+use std::process::Stdio;
 use tokio::fs::{create_dir_all, remove_dir_all, File, read_to_string};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Error, ErrorKind, Result};
 use chrono::Utc;
 
-use crate::globals::{CONTEST_LIST, JOB_LIST, USER_LIST};
-use crate::arg::{Config, Language, Problem};
+use crate::globals::{CONTEST_LIST, JOB_CACHE, JOB_QUEUE, PROBLEM_LIST, USER_LIST};
+use crate::queue::Priority;
+use crate::arg::{Case, Config, Language, Problem, SharedConfig};
+use crate::api::auth::Identity;
+use crate::api::user::Role;
 use crate::api::error::HttpError;
-use crate::sql::{insert_job, update_job};
+use crate::api::events::publish_job;
+use crate::api::problem::{in_problem_dir, problem_dir};
+use crate::api::state::{JobResult, JobState};
+use crate::storage::{storage, JobFilter, JobPage, JobSort};
+use crate::runner::{self, CompileOutcome, CompileReport, Limits, Outcome};
+
+//seconds a special judge may run before it is killed
+const SPJ_TIME_LIMIT: u64 = 10;
+//results a special judge is allowed to report
+const SPJ_RESULTS: [JobResult; 2] = [JobResult::Accepted, JobResult::WrongAnswer];
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct PostJob
@@ -32,8 +43,8 @@ pub struct Job
     pub created_time: String,
     pub updated_time: String,
     pub submission: PostJob,
-    pub state: String,
-    pub result: String,
+    pub state: JobState,
+    pub result: JobResult,
     pub score: f64,
     pub cases: Vec<JobCase>,
 }
@@ -42,56 +53,84 @@ impl Job
 {
     pub fn new(id: usize, post_job: PostJob, case_num: usize) -> Job
     {
-        let mut cases: Vec<JobCase> = Vec::new();
-        for count in 0..=case_num
-        {
-            cases.push(JobCase {
-                id: count,
-                result: "Waiting".to_string(),
-                time: 0,
-                memory: 0,
-                info: String::new(),
-            })
-        }
         Job {
             id,
             created_time: Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
             updated_time: Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
             submission: post_job.clone(),
-            state: "Queueing".to_string(),
-            result: "Waiting".to_string(),
+            state: JobState::Queueing,
+            result: JobResult::Waiting,
             score: 0.0,
-            cases,
+            cases: waiting_cases(case_num),
         }
     }
 
-    pub fn from(mut old_job: Job) -> Job
+    //reset a finished or canceled job for rejudging, with the cases its problem has now
+    pub fn from(mut old_job: Job, case_num: usize) -> Job
     {
-        for case in old_job.cases.iter_mut()
-        {
-            case.result = "Waiting".to_string();
-            case.time = 0;
-            case.memory = 0;
-            case.info = String::new();
-        }
+        debug_assert!(old_job.state.can_become(JobState::Queueing));
+        old_job.cases = waiting_cases(case_num);
         old_job.updated_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
-        old_job.state = "Queueing".to_string();
-        old_job.result = "Waiting".to_string();
+        old_job.state = JobState::Queueing;
+        old_job.result = JobResult::Waiting;
         old_job.score = 0.0;
         old_job
     }
+
+    //cases of the problem of this job as it is now, it may have changed since the submission
+    pub async fn case_num(&self) -> usize
+    {
+        match find_problem(self.submission.problem_id).await
+        {
+            Ok(problem) => problem.cases.len(),
+            //judging fails without the problem anyway
+            Err(_) => self.cases.len().saturating_sub(1),
+        }
+    }
+
+    //move along the state machine, illegal moves are refused and logged
+    pub fn set_state(&mut self, state: JobState) -> bool
+    {
+        if !self.state.can_become(state)
+        {
+            log::error!("Job {} can not go from {} to {}", self.id, self.state, state);
+            return false;
+        }
+        self.state = state;
+        self.updated_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
+        true
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct JobCase
 {
     pub id: usize,
-    pub result: String,
+    pub result: JobResult,
+    //wall clock, microseconds
     pub time: u64,
+    //user + sys, microseconds
+    #[serde(default)]
+    pub cpu_time: u64,
     pub memory: u64,
     pub info: String,
 }
 
+//case 0 for the compilation and one for each of `case_num` cases
+fn waiting_cases(case_num: usize) -> Vec<JobCase>
+{
+    (0..=case_num)
+        .map(|id| JobCase {
+            id,
+            result: JobResult::Waiting,
+            time: 0,
+            cpu_time: 0,
+            memory: 0,
+            info: String::new(),
+        })
+        .collect()
+}
+
 #[derive(Deserialize)]
 pub struct JobQuery
 {
@@ -104,176 +143,393 @@ pub struct JobQuery
     to: Option<String>,
     state: Option<String>,
     result: Option<String>,
+    //paging, either page (from 1) or the after_id cursor, all jobs without both
+    page: Option<usize>,
+    per_page: Option<usize>,
+    after_id: Option<usize>,
+    //id, created_time or score, then asc or desc
+    sort: Option<String>,
+    order: Option<String>,
+    //false leaves the source code out of each submission
+    source_code: Option<bool>,
 }
 
+//page size when only page is given, and the largest one allowed
+const DEFAULT_PER_PAGE: usize = 20;
+const MAX_PER_PAGE: usize = 1000;
+
 //gets
 #[get("/jobs/{jobid}")]
-pub async fn get_jobs_id(get_job: web::Path<usize>) -> HttpResponse
+pub async fn get_jobs_id(get_job: web::Path<usize>, identity: Option<web::ReqData<Identity>>) -> HttpResponse
 {
-    let lock = JOB_LIST.lock().await;
-    if let Some(pos) = lock.iter().position(|x| x.id == *get_job)
+    match JOB_CACHE.lock().await.get(*get_job)
     {
-        return HttpResponse::Ok()
+        Ok(Some(job)) => HttpResponse::Ok()
             .content_type("application/json")
-            .json(lock[pos].clone());
-    }
-    else
-    {
-        return HttpResponse::NotFound()
+            .json(job_json(&job, can_read_source(identity.as_deref(), &job))),
+        Ok(None) => HttpResponse::NotFound()
             .content_type("application/json")
             .json(HttpError {
                 code: 3,
                 reason: "ERR_NOT_FOUND".to_string(),
                 message: "Job ".to_string() + &get_job.to_string() + " not found."
-            });
+            }),
+        Err(_) => HttpResponse::InternalServerError()
+            .content_type("application/json")
+            .json(HttpError {
+                code: 5,
+                reason: "ERR_EXTERNAL".to_string(),
+                message: "SQL error".to_string(),
+            }),
     }
 }
 
 #[get("/jobs")]
-pub async fn get_jobs_query(query: web::Query<JobQuery>) -> HttpResponse
+pub async fn get_jobs_query(query: web::Query<JobQuery>, identity: Option<web::ReqData<Identity>>) -> HttpResponse
 {
-    let lock = JOB_LIST.lock().await;
-    let mut job_list = lock.clone();
-    drop(lock);
-
-    if let Some(user_id) = query.user_id
+    let mut filter = JobFilter {
+        user_id: query.user_id,
+        contest_id: query.contest_id,
+        problem_id: query.problem_id,
+        language: query.language.clone(),
+        from: query.from.clone(),
+        to: query.to.clone(),
+        state: None,
+        result: None,
+        ..Default::default()
+    };
+    if let Some(state) = &query.state
     {
-        job_list.retain(|x| x.submission.user_id == user_id);
+        match state.parse::<JobState>()
+        {
+            Ok(state) => filter.state = Some(state),
+            Err(message) =>
+            {
+                return HttpResponse::BadRequest()
+                    .content_type("application/json")
+                    .json(HttpError {
+                        code: 1,
+                        reason: "ERR_INVALID_ARGUMENT".to_string(),
+                        message,
+                    });
+            }
+        }
     }
-    if let Some(problem_id) = query.problem_id
+    if let Some(result) = &query.result
     {
-        job_list.retain(|x| x.submission.problem_id == problem_id);
+        match result.parse::<JobResult>()
+        {
+            Ok(result) => filter.result = Some(result),
+            Err(message) =>
+            {
+                return HttpResponse::BadRequest()
+                    .content_type("application/json")
+                    .json(HttpError {
+                        code: 1,
+                        reason: "ERR_INVALID_ARGUMENT".to_string(),
+                        message,
+                    });
+            }
+        }
     }
-    if let Some(contest_id) = query.contest_id
+    let page = match job_page(&query)
     {
-        job_list.retain(|x| x.submission.contest_id == contest_id);
-    }
-    if let Some(language) = &query.language
+        Ok(page) => page,
+        Err(message) =>
+        {
+            return HttpResponse::BadRequest()
+                .content_type("application/json")
+                .json(HttpError {
+                    code: 1,
+                    reason: "ERR_INVALID_ARGUMENT".to_string(),
+                    message,
+                });
+        }
+    };
+    if let Some(after_id) = page.after_id
     {
-        job_list.retain(|x| x.submission.language == *language);
+        match JOB_CACHE.lock().await.get(after_id)
+        {
+            Ok(Some(_)) => {}
+            Ok(None) => return job_not_found(after_id),
+            Err(_) => return sql_error(),
+        }
     }
-    if let Some(from) = &query.from
+    if let Some(user_name) = &query.user_name
     {
-        job_list.retain(|x| x.created_time >= *from);
+        //an unknown name, or one that is not user_id, matches no job
+        match USER_LIST.lock().await.iter().position(|x| x.name == *user_name)
+        {
+            Some(id) if filter.user_id.is_none_or(|x| x == id) => filter.user_id = Some(id),
+            _ => return HttpResponse::Ok()
+                .content_type("application/json")
+                .insert_header(("X-Total-Count", "0"))
+                .json(Vec::<Job>::new()),
+        }
     }
-    if let Some(to) = &query.to
+
+    let (total, job_list) = match (storage().count_jobs(&filter), storage().query_jobs(&filter, &page))
+    {
+        (Ok(total), Ok(job_list)) => (total, job_list),
+        _ => return sql_error(),
+    };
+    let job_list: Vec<serde_json::Value> = job_list.iter()
+        .map(|x| job_json(x, query.source_code != Some(false) && can_read_source(identity.as_deref(), x)))
+        .collect();
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header(("X-Total-Count", total.to_string()))
+        .json(job_list)
+}
+
+//with authentication on, contestants only see the source code of their own jobs
+fn can_read_source(identity: Option<&Identity>, job: &Job) -> bool
+{
+    match identity
     {
-        job_list.retain(|x| x.created_time <= *to);
+        Some(identity) => identity.role != Role::Contestant || identity.user_id == job.submission.user_id,
+        //GET /jobs needs a login with authentication on, so it is off
+        None => true,
     }
-    if let Some(state) = &query.state
+}
+
+pub fn job_json(job: &Job, source_code: bool) -> serde_json::Value
+{
+    let mut value = serde_json::to_value(job).unwrap();
+    if !source_code
     {
-        job_list.retain(|x| x.state >= *state);
+        if let Some(submission) = value["submission"].as_object_mut()
+        {
+            submission.remove("source_code");
+        }
     }
-    if let Some(result) = &query.result
+    value
+}
+
+//the paging and sorting asked for in a job query
+fn job_page(query: &JobQuery) -> std::result::Result<JobPage, String>
+{
+    let mut page = JobPage {
+        skip_source: query.source_code == Some(false),
+        ..Default::default()
+    };
+    if let Some(sort) = &query.sort
     {
-        job_list.retain(|x| x.result >= *result);
+        page.sort = JobSort::parse(sort).ok_or("Unknown sort ".to_string() + sort)?;
     }
-    if let Some(user_name) = &query.user_name
+    if let Some(order) = &query.order
     {
-        match USER_LIST.lock().await.iter().position(|x| x.name == *user_name)
+        page.descending = match order.as_str()
         {
-            Some(id) => job_list.retain(|y| y.submission.user_id == id),
-            None => job_list.clear(),
-        }
+            "asc" => false,
+            "desc" => true,
+            _ => return Err("Unknown order ".to_string() + order),
+        };
+    }
+    if query.page.is_some() && query.after_id.is_some()
+    {
+        return Err("Use either page or after_id".to_string());
+    }
+    if query.page.is_none() && query.after_id.is_none() && query.per_page.is_none()
+    {
+        return Ok(page);
     }
+    let per_page = query.per_page.unwrap_or(DEFAULT_PER_PAGE);
+    if per_page == 0 || per_page > MAX_PER_PAGE
+    {
+        return Err(format!("per_page must be between 1 and {}", MAX_PER_PAGE));
+    }
+    let page_number = query.page.unwrap_or(1);
+    if page_number == 0
+    {
+        return Err("page starts from 1".to_string());
+    }
+    page.limit = Some(per_page);
+    page.offset = (page_number - 1).saturating_mul(per_page);
+    page.after_id = query.after_id;
+    Ok(page)
+}
+
+#[derive(Serialize)]
+pub struct QueueInfo
+{
+    //jobs waiting for a worker
+    length: usize,
+    running: usize,
+    workers: usize,
+}
 
+#[get("/queue")]
+pub async fn get_queue(config: web::Data<SharedConfig>) -> HttpResponse
+{
+    let config = config.get();
+    let length = JOB_QUEUE.len().await;
+    let running = storage().count_jobs(&JobFilter { state: Some(JobState::Running), ..Default::default() }).unwrap_or(0);
     HttpResponse::Ok()
         .content_type("application/json")
-        .json(job_list)
+        .json(QueueInfo {
+            length,
+            running,
+            workers: config.server.judge_workers.max(1),
+        })
 }
 
 //update & delete
 #[put("/jobs/{jobid}")]
 pub async fn put_jobs_id(put_job: web::Path<usize>) -> HttpResponse
 {
-    let mut lock = JOB_LIST.lock().await;
-    if let Some(pos) = lock.iter().position(|x| x.id == *put_job)
+    let mut cache = JOB_CACHE.lock().await;
+    let job = match cache.get(*put_job)
     {
-        if lock[pos].state != "Finished"
-        {
-            return HttpResponse::BadRequest()
-                .content_type("application/json")
-                .json(HttpError {
-                    code: 2,
-                    reason: "ERR_INVALID_STATE".to_string(),
-                    message: "Job ".to_string() + &put_job.to_string() + " not finished."
-                });
-        }
-        lock[pos] = Job::from(lock[pos].clone());
-        if let Err(_) = update_job(&lock[pos]).await
-        {
-            return HttpResponse::InternalServerError()
-                .content_type("application/json")
-                .json(HttpError {
-                    code: 5,
-                    reason: "ERR_EXTERNAL".to_string(),
-                    message: "SQL error".to_string(),
-                })
-        }
-        return HttpResponse::Ok()
+        Ok(Some(job)) => job,
+        Ok(None) => return job_not_found(*put_job),
+        Err(_) => return sql_error(),
+    };
+    if !job.state.can_become(JobState::Queueing)
+    {
+        return HttpResponse::BadRequest()
             .content_type("application/json")
-            .json(lock[pos].clone());
+            .json(HttpError {
+                code: 2,
+                reason: "ERR_INVALID_STATE".to_string(),
+                message: "Job ".to_string() + &put_job.to_string() + " not finished."
+            });
     }
-    else
+    let case_num = job.case_num().await;
+    let job = Job::from(job, case_num);
+    if storage().update_job(&job).is_err()
     {
-        return HttpResponse::NotFound()
+        return sql_error();
+    }
+    cache.keep(&job);
+    JOB_QUEUE.push(*put_job, Priority::Low).await;
+    publish_job(&job);
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .json(job)
+}
+
+//stop a queued job from being judged, it can be rejudged later
+#[post("/jobs/{jobid}/cancel")]
+pub async fn cancel_jobs_id(cancel_job: web::Path<usize>) -> HttpResponse
+{
+    let mut cache = JOB_CACHE.lock().await;
+    let mut job = match cache.get(*cancel_job)
+    {
+        Ok(Some(job)) => job,
+        Ok(None) => return job_not_found(*cancel_job),
+        Err(_) => return sql_error(),
+    };
+    if let Some(response) = rejudging_error(*cancel_job)
+    {
+        return response;
+    }
+    if !job.set_state(JobState::Canceled)
+    {
+        return HttpResponse::BadRequest()
             .content_type("application/json")
             .json(HttpError {
-                code: 3,
-                reason: "ERR_NOT_FOUND".to_string(),
-                message: "Job ".to_string() + &put_job.to_string() + " not found."
+                code: 2,
+                reason: "ERR_INVALID_STATE".to_string(),
+                message: "Job ".to_string() + &cancel_job.to_string() + " not queueing."
             });
     }
+    JOB_QUEUE.remove(*cancel_job).await;
+    if storage().update_job(&job).is_err()
+    {
+        return sql_error();
+    }
+    cache.keep(&job);
+    publish_job(&job);
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .json(job)
 }
 
 #[delete("/jobs/{jobid}")]
 pub async fn delete_jobs(delete_job: web::Path<usize>) -> HttpResponse
 {
-    let mut lock = JOB_LIST.lock().await;
-    if let Some(pos) = lock.iter().position(|x| x.id == *delete_job)
+    let mut cache = JOB_CACHE.lock().await;
+    let job = match cache.get(*delete_job)
     {
-        if lock[pos].state == "Queueing"
-        {
-            lock.remove(pos);
-            if let Err(_) = crate::sql::delete_job(*delete_job).await
-            {
-                return HttpResponse::InternalServerError()
-                    .content_type("application/json")
-                    .json(HttpError {
-                        code: 5,
-                        reason: "ERR_EXTERNAL".to_string(),
-                        message: "SQL error".to_string(),
-                    })
-            }
-        }
-        else
-        {
-            return HttpResponse::BadRequest()
-                .content_type("application")
-                .json(HttpError {
-                    code: 2,
-                    reason: "ERR_NOT_FOUND".to_string(),
-                    message: "Job ".to_string() + &delete_job.to_string() + " not queueing."
-                });
-        }
+        Ok(Some(job)) => job,
+        Ok(None) => return job_not_found(*delete_job),
+        Err(_) => return sql_error(),
+    };
+    if let Some(response) = rejudging_error(*delete_job)
+    {
+        return response;
     }
-    else
+    if job.state != JobState::Queueing
     {
-        return HttpResponse::NotFound()
-        .content_type("application")
+        return HttpResponse::BadRequest()
+            .content_type("application/json")
+            .json(HttpError {
+                code: 2,
+                reason: "ERR_INVALID_STATE".to_string(),
+                message: "Job ".to_string() + &delete_job.to_string() + " not queueing."
+            });
+    }
+    JOB_QUEUE.remove(*delete_job).await;
+    if storage().delete_job(*delete_job).is_err()
+    {
+        return sql_error();
+    }
+    cache.remove(*delete_job);
+    HttpResponse::Ok().into()
+}
+
+//a rejudge waits for each of its jobs to finish again, they may not leave the queue
+//the error response if this job is in an open rejudge
+fn rejudging_error(id: usize) -> Option<HttpResponse>
+{
+    match storage().rejudge_of(id)
+    {
+        Ok(None) => None,
+        Ok(Some(rejudge_id)) => Some(HttpResponse::BadRequest()
+            .content_type("application/json")
+            .json(HttpError {
+                code: 2,
+                reason: "ERR_INVALID_STATE".to_string(),
+                message: "Job ".to_string() + &id.to_string() + " is in rejudge " + &rejudge_id.to_string() + "."
+            })),
+        Err(_) => Some(sql_error()),
+    }
+}
+
+fn job_not_found(id: usize) -> HttpResponse
+{
+    HttpResponse::NotFound()
+        .content_type("application/json")
         .json(HttpError {
             code: 3,
             reason: "ERR_NOT_FOUND".to_string(),
-            message: "Job ".to_string() + &delete_job.to_string() + " not found."
-        });
-    }
-    HttpResponse::Ok().into()
+            message: "Job ".to_string() + &id.to_string() + " not found."
+        })
+}
+
+pub fn sql_error() -> HttpResponse
+{
+    HttpResponse::InternalServerError()
+        .content_type("application/json")
+        .json(HttpError {
+            code: 5,
+            reason: "ERR_EXTERNAL".to_string(),
+            message: "SQL error".to_string(),
+        })
 }
 
 //submit
 #[post("/jobs")]
-pub async fn post_jobs(post_job: web::Json<PostJob>, config: web::Data<Config>) -> HttpResponse
+pub async fn post_jobs(post_job: web::Json<PostJob>, config: web::Data<SharedConfig>, identity: Option<web::ReqData<Identity>>) -> HttpResponse
 {
+    let config = config.get();
+    //with authentication on, jobs are submitted as the logged in user
+    let mut post_job = post_job.into_inner();
+    if let Some(identity) = identity
+    {
+        post_job.user_id = identity.user_id;
+    }
 
     //wrong user
     let lock = USER_LIST.lock().await;
@@ -296,7 +552,7 @@ pub async fn post_jobs(post_job: web::Json<PostJob>, config: web::Data<Config>)
         {
             Some(contest) =>
             {
-                if !contest.user_ids.iter().any(|&x| x == post_job.user_id)
+                if !contest.user_ids.contains(&post_job.user_id)
                 {
                     return HttpResponse::BadRequest()
                         .content_type("application/json")
@@ -306,7 +562,7 @@ pub async fn post_jobs(post_job: web::Json<PostJob>, config: web::Data<Config>)
                             message: "User not in contest".to_string(),
                         });
                 }
-                if !contest.problem_ids.iter().any(|&x| x == post_job.problem_id)
+                if !contest.problem_ids.contains(&post_job.problem_id)
                 {
                     return HttpResponse::BadRequest()
                         .content_type("application/json")
@@ -327,14 +583,15 @@ pub async fn post_jobs(post_job: web::Json<PostJob>, config: web::Data<Config>)
                         message: "Time not in contest".to_string(),
                     });
                 }
-                let mut count: usize = 0;
-                for job in JOB_LIST.lock().await.iter()
+                let count = match storage().count_jobs(&JobFilter {
+                    user_id: Some(post_job.user_id),
+                    problem_id: Some(post_job.problem_id),
+                    ..Default::default()
+                })
                 {
-                    if job.submission.user_id == post_job.user_id && job.submission.problem_id == post_job.problem_id
-                    {
-                        count += 1;
-                    }
-                }
+                    Ok(count) => count,
+                    Err(_) => return sql_error(),
+                };
                 if contest.submission_limit != 0 && contest.submission_limit <= count
                 {
                     return HttpResponse::BadRequest()
@@ -372,229 +629,350 @@ pub async fn post_jobs(post_job: web::Json<PostJob>, config: web::Data<Config>)
     }
 
     //find the problem
-    let mut problem = Problem {
-        id: 0,
-        name: String::new(),
-        misc: serde_json::Value::Object(Map::new()),
-        problem_type: String::new(),
-        cases: Vec::new(),
-    };
-    if let Ok(tmp) = find_problem(&config.problems, post_job.problem_id).await
+    let problem = match find_problem(post_job.problem_id).await
     {
-        problem = tmp
-    }
-    else
-    {
-        return HttpResponse::NotFound()
-            .content_type("application/json")
-            .json(HttpError {
-                code: 3,
-                reason: "ERR_NOT_FOUND".to_string(),
-                message: "Problem ".to_string() + &post_job.problem_id.to_string() + " not found."
-            });
-    }
+        Ok(tmp) => tmp,
+        Err(_) =>
+        {
+            return HttpResponse::NotFound()
+                .content_type("application/json")
+                .json(HttpError {
+                    code: 3,
+                    reason: "ERR_NOT_FOUND".to_string(),
+                    message: "Problem ".to_string() + &post_job.problem_id.to_string() + " not found."
+                });
+        }
+    };
 
     //put the job in the test queue
-    let mut lock = JOB_LIST.lock().await;
-    let id = match lock.last()
+    //the cache lock keeps two submissions from taking the same id
+    let mut cache = JOB_CACHE.lock().await;
+    let id = match storage().next_job_id()
     {
-        Some(job) => job.id + 1,
-        None => 0,
+        Ok(id) => id,
+        Err(_) => return sql_error(),
     };
     let job = Job::new(id, post_job.clone(), problem.cases.len());
-    lock.push(job.clone());
-    if let Err(_) = insert_job(&job).await
+    if storage().insert_job(&job).is_err()
     {
-        return HttpResponse::InternalServerError()
-                .content_type("application/json")
-                .json(HttpError {
-                    code: 5,
-                    reason: "ERR_EXTERNAL".to_string(),
-                    message: "SQL error".to_string(),
-                })
+        return sql_error();
     }
-    drop(lock);
+    cache.keep(&job);
+    JOB_QUEUE.push(id, Priority::Normal).await;
+    publish_job(&job);
+    drop(cache);
 
     HttpResponse::Ok()
         .content_type("application/json")
         .json(job)
 }
 
-//test function
-pub async fn job_producer(tx_origin: mpsc::Sender<Job>, config_origin: Config)
+//judge worker, takes queued jobs one at a time
+//each job is judged with the config of the moment it was claimed
+pub async fn job_worker(tx: mpsc::Sender<Job>, config: web::Data<SharedConfig>)
 {
-    //check the test queue again and again
     loop
     {
-        let lock = JOB_LIST.lock().await;
-        let job_list = lock.clone();
-        drop(lock);
-        for mut job in job_list.into_iter()
+        if tx.is_closed()
         {
-            //find jobs that need test
-            if job.state != "Queueing"
+            log::error!("Judge worker stopped, the job consumer is gone");
+            return;
+        }
+        let id = JOB_QUEUE.pop().await;
+        if let Some(job) = claim_job(id).await
+        {
+            //once claimed, send to receiver to save the running state
+            report(&tx, &job).await;
+            judge_job(job, &tx, &config.get()).await;
+        }
+    }
+}
+
+//hand a snapshot of the job to job_consumer, which saves and publishes it
+async fn report(tx: &mpsc::Sender<Job>, job: &Job)
+{
+    if tx.send(job.clone()).await.is_err()
+    {
+        log::error!("Job {} not saved, the job consumer is gone", job.id);
+    }
+}
+
+//mark a queued job as running, None if it was deleted or taken meanwhile
+async fn claim_job(id: usize) -> Option<Job>
+{
+    let mut cache = JOB_CACHE.lock().await;
+    let mut job = cache.get(id).ok()??;
+    //canceled or deleted after it was queued, not an error
+    if job.state != JobState::Queueing || !job.set_state(JobState::Running)
+    {
+        return None;
+    }
+    //the problem may have been edited while the job was queued
+    job.cases = waiting_cases(job.case_num().await);
+    job.result = JobResult::Running;
+    job.cases[0].result = JobResult::Running;
+    cache.keep(&job);
+    Some(job)
+}
+
+//jobs left running by a previous server are queued again,
+//or finished as System Error with `fail` for a later manual rejudge
+pub async fn recover_interrupted_jobs(fail: bool)
+{
+    let running = match storage().query_jobs(&JobFilter { state: Some(JobState::Running), ..Default::default() }, &JobPage::default())
+    {
+        Ok(running) => running,
+        Err(err) =>
+        {
+            log::error!("Failed to read interrupted jobs: {}", err);
+            return;
+        }
+    };
+    for mut job in running
+    {
+        for case in job.cases.iter_mut().filter(|x| x.result == JobResult::Running)
+        {
+            case.result = JobResult::SystemError;
+        }
+        job.result = JobResult::SystemError;
+        job.set_state(JobState::Finished);
+        if !fail
+        {
+            let case_num = job.case_num().await;
+            job = Job::from(job, case_num);
+        }
+        //left as running in the database, the next start tries again
+        if let Err(err) = storage().update_job(&job)
+        {
+            log::error!("Failed to recover interrupted job {}: {}", job.id, err);
+            continue;
+        }
+        log::warn!("Job {} was interrupted, now {}", job.id, job.state);
+    }
+}
+
+//queue the jobs in the database that are still waiting
+pub async fn queue_waiting_jobs()
+{
+    match storage().query_jobs(&JobFilter { state: Some(JobState::Queueing), ..Default::default() }, &JobPage::default())
+    {
+        Ok(waiting) =>
+        {
+            for job in waiting
             {
-                continue;
+                JOB_QUEUE.push(job.id, Priority::Normal).await;
             }
-            let tx = tx_origin.clone();
-            let config = config_origin.clone();
-            //并行测评的异步线程
-            tokio::spawn(async move {
-            job.state = "Running".to_string();
-            job.result = "Running".to_string();
-            job.cases[0].result = "Running".to_string();
-            job.updated_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
-            //once updated, send to receiver to update the queue
-            tx.send(job.clone()).await.unwrap();
-            
-            //create tmp dir for test
-            let _ = create_dir_all("./tmp_code_runner/".to_string() + &job.id.to_string()).await;
-            let path = "./tmp_code_runner/".to_string() + &job.id.to_string() + "/";
-            let problem = find_problem(&config.problems, job.submission.problem_id).await.unwrap();
-
-            //compilation
-            match compile_program(&path, &job.submission, &config.languages).await
+        }
+        Err(err) => log::error!("Failed to read waiting jobs: {}", err),
+    }
+}
+
+async fn judge_job(mut job: Job, tx: &mpsc::Sender<Job>, config: &Config)
+{
+    //create tmp dir for test
+    let _ = create_dir_all("./tmp_code_runner/".to_string() + &job.id.to_string()).await;
+    let path = "./tmp_code_runner/".to_string() + &job.id.to_string() + "/";
+    //problems in use can not be deleted, but be safe
+    let problem = match find_problem(job.submission.problem_id).await
+    {
+        Ok(problem) => problem,
+        Err(err) =>
+        {
+            job.result = JobResult::SystemError;
+            job.cases[0].result = JobResult::SystemError;
+            job.cases[0].info = err.to_string();
+            job.set_state(JobState::Finished);
+            report(tx, &job).await;
+            let _ = remove_dir_all("./tmp_code_runner/".to_string() + &job.id.to_string()).await;
+            return;
+        }
+    };
+
+    //compilation
+    let compiled = match compile_program(&path, &job.submission, &config.languages).await
+    {
+        Ok(report) =>
+        {
+            job.cases[0].info = report.info;
+            match report.outcome
             {
-                Ok(status) =>
-                {
-                    if status.success()
-                    {
-                        job.cases[0].result = "Compilation Success".to_string();
-                        job.updated_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
-                        tx.send(job.clone()).await.unwrap();
-                    }
-                    else
-                    {
-                        job.result = "Compilation Error".to_string();
-                        job.cases[0].result = "Compilation Error".to_string();
-                        job.state = "Finished".to_string();
-                        job.updated_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
-                        tx.send(job.clone()).await.unwrap();
-                        let _ = remove_dir_all("./tmp_code_runner/".to_string() + &job.id.to_string()).await;
-                        return;
-                    }
-                }
-                Err(_) =>
-                {
-                    job.result = "Compilation Error".to_string();
-                    job.cases[0].result = "Compilation Error".to_string();
-                    job.state = "Finished".to_string();
-                    job.updated_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
-                    tx.send(job.clone()).await.unwrap();
-                    let _ = remove_dir_all("./tmp_code_runner/".to_string() + &job.id.to_string()).await;
-                    return;
-                }
+                CompileOutcome::Success => None,
+                CompileOutcome::Error => Some(JobResult::CompilationError),
+                CompileOutcome::Timeout => Some(JobResult::CompilationTimeout),
             }
+        }
+        //an unknown language is the submission's fault, anything else is ours
+        Err(err) if err.kind() == ErrorKind::Unsupported =>
+        {
+            job.cases[0].info = err.to_string();
+            Some(JobResult::CompilationError)
+        }
+        Err(err) =>
+        {
+            job.cases[0].info = err.to_string();
+            Some(JobResult::SystemError)
+        }
+    };
+    match compiled
+    {
+        None =>
+        {
+            job.cases[0].result = JobResult::CompilationSuccess;
+            job.updated_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
+            report(tx, &job).await;
+        }
+        Some(result) =>
+        {
+            job.result = result;
+            job.cases[0].result = result;
+            job.set_state(JobState::Finished);
+            report(tx, &job).await;
+            let _ = remove_dir_all("./tmp_code_runner/".to_string() + &job.id.to_string()).await;
+            return;
+        }
+    }
 
-            //test cases
-            let mut count: usize = 1;
-            for case in problem.cases.iter()
+    //test cases, a pack only gets its score when all of its cases pass
+    //for dynamic ranking the rest of the score is given in the ranklist
+    let score_rate = 1.0 - dynamic_ranking_ratio(&problem);
+    let sandbox = match config.languages.iter().find(|x| x.name == job.submission.language)
+    {
+        Some(language) if language.isolation => Some(path.clone() + "sandbox"),
+        _ => None,
+    };
+    let dir = problem_dir(config, problem.id);
+    for pack in get_packs(&problem)
+    {
+        let mut passed: bool = true;
+        let mut pack_score: f64 = 0.0;
+        for count in pack
+        {
+            //the problem was edited again after the job was claimed, the case is not there
+            if count >= job.cases.len()
             {
-                job.cases[count].result = "Running".to_string();
+                passed = false;
+                if job.result == JobResult::Running { job.result = JobResult::SystemError; }
+                continue;
+            }
+            if !passed
+            {
+                job.cases[count].result = JobResult::Skipped;
                 job.updated_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
-                tx.send(job.clone()).await.unwrap();
-                let start = Utc::now();
-                let mut memory: u64 = 0;
-                match run_case(&path, &case.input_file, &(path.clone() + &count.to_string() + ".out"), problem.cases[count - 1].time_limit, problem.cases[count - 1].memory_limit, &mut memory).await
-                {
-                    Some(status) =>
-                    {
-                        if status == 0 //normal exit
-                        {
-                            let end = Utc::now();
-                            let duration: u64 = (end - start).num_microseconds().unwrap() as u64;
-                            let mut same: bool = true;
-                            //compare answer
-                            if problem.problem_type == "standard"
-                            {
-                                let output_file = File::open(path.clone() + &count.to_string() + ".out").await.unwrap();
-                                let output = BufReader::new(output_file);
-                                let mut output_lines: Vec<String> = Vec::new();
-                                let answer_file = File::open(case.answer_file.clone()).await.unwrap();
-                                let answer = BufReader::new(answer_file);
-                                let mut answer_lines: Vec<String> = Vec::new();
-                                let mut output_iter = output.lines();
-                                while let Some(line) = output_iter.next_line().await.unwrap()
-                                {
-                                    if line.trim_end() == "" { continue; }
-                                    output_lines.push(line.trim_end().to_string());
-                                }
-                                let mut answer_iter = answer.lines();
-                                while let Some(line) = answer_iter.next_line().await.unwrap()
-                                {
-                                    if line.trim_end() == "" { continue; }
-                                    answer_lines.push(line.trim_end().to_string());
-                                }
-                                if output_lines != answer_lines
-                                {
-                                    same = false;
-                                }
-                            }
-                            else
-                            {
-                                same = read_to_string(path.clone() + &count.to_string() + ".out").await.unwrap() 
-                                    == read_to_string(case.answer_file.clone()).await.unwrap();
-                            }
-                            if same
-                            {
-                                job.cases[count].result = "Accepted".to_string();
-                                job.cases[count].time = duration;
-                                job.cases[count].memory = memory;
-                                job.score += problem.cases[count - 1].score;
-                                job.updated_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
-                                tx.send(job.clone()).await.unwrap();
-                            }
-                            else
-                            {
-                                job.cases[count].result = "Wrong Answer".to_string();
-                                job.cases[count].time = duration;
-                                job.cases[count].memory = memory;
-                                if job.result == "Running" { job.result = "Wrong Answer".to_string(); }
-                                job.updated_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
-                                tx.send(job.clone()).await.unwrap();
-                            }
-                        }
-                        //MLE
-                        else if status == -1
-                        {
-                            job.cases[count].result = "Memory Limit Exceeded".to_string();
-                            if job.result == "Running" { job.result = "Memory Limit Exceeded".to_string(); }
-                            job.updated_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
-                            tx.send(job.clone()).await.unwrap();
-                        }
-                        //abnormal exit: RE
-                        else
-                        {
-                            job.cases[count].result = "Runtime Error".to_string();
-                            if job.result == "Running" { job.result = "Runtime Error".to_string(); }
-                            job.updated_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
-                            tx.send(job.clone()).await.unwrap();
-                        }
-                    }
-                    //TLE
-                    None =>
-                    {
-                        job.cases[count].result = "Time Limit Exceeded".to_string();
-                        if job.result == "Running" { job.result = "Time Limit Exceeded".to_string(); }
-                        job.updated_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
-                        tx.send(job.clone()).await.unwrap();
-                    }
-                }
-                count += 1;
+                report(tx, &job).await;
+                continue;
+            }
+            job.cases[count].result = JobResult::Running;
+            job.updated_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
+            report(tx, &job).await;
+
+            judge_case(&path, sandbox.as_deref(), config.server.wall_time_ratio, &dir, &problem, count, &mut job.cases[count]).await;
+            if job.cases[count].result == JobResult::Accepted
+            {
+                pack_score += problem.cases[count - 1].score;
             }
-            if job.result == "Running"
+            else
             {
-                job.result = "Accepted".to_string();
+                passed = false;
+                if job.result == JobResult::Running { job.result = job.cases[count].result; }
             }
-            job.state = "Finished".to_string();
             job.updated_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
-            tx.send(job.clone()).await.unwrap();
-    
-            let _ = remove_dir_all("./tmp_code_runner/".to_string() + &job.id.to_string()).await;
-        });
+            report(tx, &job).await;
+        }
+        if passed
+        {
+            job.score += pack_score * score_rate;
+        }
+    }
+    if job.result == JobResult::Running
+    {
+        job.result = JobResult::Accepted;
+    }
+    job.set_state(JobState::Finished);
+    report(tx, &job).await;
+
+    let _ = remove_dir_all("./tmp_code_runner/".to_string() + &job.id.to_string()).await;
+}
+
+//the part of the score given by relative performance, 0 if not a dynamic ranking problem
+pub fn dynamic_ranking_ratio(problem: &Problem) -> f64
+{
+    if problem.problem_type != "dynamic_ranking"
+    {
+        return 0.0;
+    }
+    problem.misc.get("dynamic_ranking_ratio")
+        .and_then(|x| x.as_f64())
+        .unwrap_or(0.0)
+        .clamp(0.0, 1.0)
+}
+
+//split cases into packs by misc.packing, cases not in any pack are judged alone
+pub fn get_packs(problem: &Problem) -> Vec<Vec<usize>>
+{
+    let case_num = problem.cases.len();
+    let mut packs: Vec<Vec<usize>> = Vec::new();
+    let mut packed: Vec<bool> = vec![false; case_num + 1];
+    if let Some(packing) = problem.misc.get("packing").and_then(|x| x.as_array())
+    {
+        for pack in packing.iter().filter_map(|x| x.as_array())
+        {
+            let cases: Vec<usize> = pack.iter()
+                .filter_map(|x| x.as_u64())
+                .map(|x| x as usize)
+                .filter(|&x| x >= 1 && x <= case_num && !packed[x])
+                .collect();
+            for &count in cases.iter()
+            {
+                packed[count] = true;
+            }
+            if !cases.is_empty()
+            {
+                packs.push(cases);
+            }
         }
-        time::sleep(time::Duration::from_millis(500)).await;
     }
+    for (count, _) in packed.iter().enumerate().skip(1).filter(|(_, &x)| !x)
+    {
+        packs.push(vec![count]);
+    }
+    packs.sort_by_key(|x| x[0]);
+    packs
+}
+
+//run case `count` of the problem and fill in its result, `dir` is the data directory of the problem
+async fn judge_case(path: &str, sandbox: Option<&str>, wall_time_ratio: f64, dir: &str, problem: &Problem, count: usize, job_case: &mut JobCase)
+{
+    let case = &problem.cases[count - 1];
+    if !in_problem_dir(dir, &case.input_file).await || !in_problem_dir(dir, &case.answer_file).await
+    {
+        job_case.result = JobResult::SystemError;
+        job_case.info = "Test data is outside the problem data.".to_string();
+        return;
+    }
+    let out_file = path.to_string() + &count.to_string() + ".out";
+    let limits = Limits {
+        time_limit: case.time_limit,
+        wall_time_limit: (case.time_limit as f64 * wall_time_ratio) as u64,
+        memory_limit: case.memory_limit,
+    };
+    let report = runner::run(&(path.to_string() + "main"), &case.input_file, &out_file, limits, sandbox).await;
+    job_case.time = report.time;
+    job_case.cpu_time = report.cpu_time;
+    job_case.memory = report.memory;
+    job_case.result = match report.outcome
+    {
+        Outcome::Exited =>
+        {
+            //compare answer
+            let (result, info) = check_output(problem, case, &out_file).await;
+            job_case.info = info;
+            result
+        }
+        Outcome::RuntimeError => JobResult::RuntimeError,
+        Outcome::TimeLimitExceeded => JobResult::TimeLimitExceeded,
+        Outcome::MemoryLimitExceeded => JobResult::MemoryLimitExceeded,
+        Outcome::SystemError => JobResult::SystemError,
+    };
 }
 
 //update function
@@ -603,60 +981,115 @@ pub async fn job_consumer(mut rx: mpsc::Receiver<Job>)
     //receive the updated job from sender
     while let Some(job) = rx.recv().await
     {
-        let mut lock = JOB_LIST.lock().await;
-        lock[job.id] = job.clone();
-        let _ = update_job(&job).await;
-        drop(lock);
+        let mut cache = JOB_CACHE.lock().await;
+        let _ = storage().update_job(&job);
+        cache.keep(&job);
+        publish_job(&job);
+        drop(cache);
     }
 }
 
-//code runner
-async fn run_case(path: &str, in_file: &str, out_file: &str, time_limit: u64, memory_limit: u64, memory: &mut u64) -> Option<i64>
+//compare the output of a case with its answer, returns (result, info)
+async fn check_output(problem: &Problem, case: &Case, out_file: &str) -> (JobResult, String)
 {
-    match Command::new("./tmp_code_runner/run")
-        .arg("-p")
-        .arg(path)
-        .arg("-i")
-        .arg(in_file)
-        .arg("-o")
-        .arg(out_file)
-        .arg("-t")
-        .arg(time_limit.to_string())
-        .arg("-m")
-        .arg(memory_limit.to_string())
-        .stdout(File::create(path.to_string() + "run.out").await.unwrap().into_std().await)
-        .spawn()
-        .unwrap()
-        .wait()
-        .await
+    match problem.problem_type.as_str()
+    {
+        "standard" | "dynamic_ranking" =>
+        {
+            //ignore trailing spaces and blank lines
+            let output_lines = read_trimmed_lines(out_file).await;
+            let answer_lines = read_trimmed_lines(&case.answer_file).await;
+            match (output_lines, answer_lines)
+            {
+                (Ok(output), Ok(answer)) if output == answer => (JobResult::Accepted, String::new()),
+                _ => (JobResult::WrongAnswer, String::new()),
+            }
+        }
+        "spj" => special_judge(problem, case, out_file).await,
+        _ =>
+        {
+            let output = read_to_string(out_file).await.unwrap_or_default();
+            let answer = read_to_string(&case.answer_file).await.unwrap_or_default();
+            if output == answer
+            {
+                (JobResult::Accepted, String::new())
+            }
+            else
+            {
+                (JobResult::WrongAnswer, String::new())
+            }
+        }
+    }
+}
+
+async fn read_trimmed_lines(file_name: &str) -> Result<Vec<String>>
+{
+    let file = File::open(file_name).await?;
+    let mut reader = BufReader::new(file).lines();
+    let mut lines: Vec<String> = Vec::new();
+    while let Some(line) = reader.next_line().await?
     {
-        Ok(_) => (),
-        Err(_) => return Some(-1),
+        if line.trim_end().is_empty() { continue; }
+        lines.push(line.trim_end().to_string());
     }
+    Ok(lines)
+}
 
-    //get exit status
-    let result_file = File::open(path.to_string() + "run.out").await.unwrap();
-    let mut reader = BufReader::new(result_file).lines();
-    let mut result: Vec<i64> = Vec::new();
-    while let Some(line) = reader.next_line().await.unwrap()
+//run misc.special_judge, its first output line is the result and the second one is the info
+async fn special_judge(problem: &Problem, case: &Case, out_file: &str) -> (JobResult, String)
+{
+    let spj_error = |info: &str| (JobResult::SpjError, info.to_string());
+
+    let mut args: Vec<String> = match problem.misc.get("special_judge").and_then(|x| x.as_array())
     {
-        result.push(line.trim().parse().unwrap());
+        Some(command) => command.iter()
+            .filter_map(|x| x.as_str())
+            .map(|x| x.replace("%OUTPUT%", out_file)
+                .replace("%ANSWER%", &case.answer_file)
+                .replace("%INPUT%", &case.input_file))
+            .collect(),
+        None => return spj_error("No special judge command"),
+    };
+    if args.is_empty()
+    {
+        return spj_error("No special judge command");
     }
-    if result.is_empty()
+    let command = args.remove(0);
+
+    let child = match Command::new(command)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(_) => return spj_error("Failed to start special judge"),
+    };
+    let output = match time::timeout(time::Duration::from_secs(SPJ_TIME_LIMIT), child.wait_with_output()).await
     {
-        None
+        Ok(Ok(output)) => output,
+        Ok(Err(_)) => return spj_error("Failed to run special judge"),
+        Err(_) => return spj_error("Special judge timed out"),
+    };
+    if !output.status.success()
+    {
+        return spj_error("Special judge exited abnormally");
     }
-    else
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let mut lines = stdout.lines();
+    let result = lines.next().unwrap_or("").trim().to_string();
+    let info = lines.next().unwrap_or("").trim().to_string();
+    match result.parse::<JobResult>()
     {
-        if result[0] == 0
-        {
-            *memory = result[1] as u64;
-        }
-        Some(result[0])
+        Ok(result) if SPJ_RESULTS.contains(&result) => (result, info),
+        _ => spj_error(&("Unknown special judge result: ".to_string() + &result)),
     }
 }
 
-async fn compile_program(path: &str, job: &PostJob, languages: &Vec<Language>) -> Result<ExitStatus>
+async fn compile_program(path: &str, job: &PostJob, languages: &[Language]) -> Result<CompileReport>
 {
     if let Some(language) = languages.iter().find(|&x| x.name == job.language)
     {
@@ -673,9 +1106,11 @@ async fn compile_program(path: &str, job: &PostJob, languages: &Vec<Language>) -
                 *arg = path.to_string() + "main";
             }
         }
-        let mut src = File::create(path.to_string() + &language.file_name).await.unwrap();
-        let _ = src.write(job.source_code.as_bytes()).await;
-        Command::new(command).args(args).status().await
+        let mut src = File::create(path.to_string() + &language.file_name).await
+            .map_err(|err| Error::new(err.kind(), "Failed to save the source code: ".to_string() + &err.to_string()))?;
+        src.write_all(job.source_code.as_bytes()).await
+            .map_err(|err| Error::new(err.kind(), "Failed to save the source code: ".to_string() + &err.to_string()))?;
+        runner::compile(&command, &args, language.compile_time_limit, language.compile_memory_limit).await
     }
     else
     {
@@ -683,14 +1118,60 @@ async fn compile_program(path: &str, job: &PostJob, languages: &Vec<Language>) -
     }
 }
 
-async fn find_problem(problems: &Vec<Problem>, problem_id: usize) -> Result<Problem>
+async fn find_problem(problem_id: usize) -> Result<Problem>
 {
-    for tmp in problems.clone().into_iter()
+    for tmp in PROBLEM_LIST.lock().await.iter().cloned()
     {
         if tmp.id == problem_id
         {
             return Ok(tmp)
         }
     }
-    return Err(Error::new(ErrorKind::NotFound, "No Such Problem"))
-}
\ No newline at end of file
+    Err(Error::new(ErrorKind::NotFound, "No Such Problem"))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn problem(case_num: usize, misc: serde_json::Value) -> Problem
+    {
+        let case = serde_json::json!({
+            "score": 10.0,
+            "input_file": "1.in",
+            "answer_file": "1.ans",
+            "time_limit": 1000000,
+            "memory_limit": 0,
+        });
+        serde_json::from_value(serde_json::json!({
+            "id": 0,
+            "name": "packed",
+            "type": "standard",
+            "misc": misc,
+            "cases": vec![case; case_num],
+        })).unwrap()
+    }
+
+    #[test]
+    fn cases_without_packing_are_judged_alone()
+    {
+        assert_eq!(get_packs(&problem(3, serde_json::json!({}))), [vec![1], vec![2], vec![3]]);
+        assert_eq!(get_packs(&problem(0, serde_json::json!({"packing": [[1]]}))), Vec::<Vec<usize>>::new());
+    }
+
+    #[test]
+    fn packs_come_in_order_of_their_first_case()
+    {
+        let packs = get_packs(&problem(6, serde_json::json!({"packing": [[4, 5], [1, 2]]})));
+        assert_eq!(packs, [vec![1, 2], vec![3], vec![4, 5], vec![6]]);
+    }
+
+    #[test]
+    fn unknown_and_repeated_cases_are_left_out_of_packs()
+    {
+        //case 2 belongs to the first pack naming it, 0 and 7 are no cases
+        let packs = get_packs(&problem(4, serde_json::json!({"packing": [[1, 2], [0, 2, 7], [3, "4"]]})));
+        assert_eq!(packs, [vec![1, 2], vec![3], vec![4]]);
+    }
+}
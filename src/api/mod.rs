@@ -1,4 +1,11 @@
 pub mod job;
+pub mod state;
+pub mod events;
+pub mod rejudge;
 pub mod error;
+pub mod auth;
 pub mod user;
-pub mod contest;
\ No newline at end of file
+pub mod problem;
+pub mod reload;
+pub mod contest;
+pub mod scoreboard;
\ No newline at end of file
@@ -0,0 +1,653 @@
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Error, ErrorKind, Read};
+use std::path::{Component, Path};
+use tokio::fs::{canonicalize, read_to_string, remove_dir_all, rename};
+use chrono::Utc;
+
+use crate::arg::{Case, Config, Problem, SharedConfig};
+use crate::globals::{CONTEST_LIST, PROBLEM_LIST};
+use crate::api::auth::Identity;
+use crate::api::error::HttpError;
+use crate::api::user::Role;
+use crate::storage::{storage, JobFilter};
+use crate::validate::{self, Severity};
+
+//largest test data archive accepted, bytes
+pub const UPLOAD_LIMIT: usize = 256 * 1024 * 1024;
+
+//problem metadata as sent by clients
+//case files are named relative to the problem's data directory
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PostProblem
+{
+    pub id: Option<usize>,
+    pub name: String,
+    #[serde(rename = "type", default = "default_problem_type")]
+    pub problem_type: String,
+    #[serde(default = "default_misc")]
+    pub misc: serde_json::Value,
+    #[serde(default)]
+    pub statement: String,
+    #[serde(default)]
+    pub input_format: String,
+    #[serde(default)]
+    pub output_format: String,
+    pub cases: Vec<Case>,
+}
+
+//a problem as shown to contestants, with the data of its samples
+//cases and misc are only filled in for those who may edit the problem
+#[derive(Debug, Serialize)]
+pub struct ProblemView
+{
+    pub id: usize,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub statement: String,
+    pub input_format: String,
+    pub output_format: String,
+    pub limits: ProblemLimits,
+    pub samples: Vec<Sample>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub misc: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cases: Option<Vec<Case>>,
+}
+
+//the largest limits among the cases
+#[derive(Debug, Serialize)]
+pub struct ProblemLimits
+{
+    pub time_limit: u64,
+    pub memory_limit: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Sample
+{
+    pub input: String,
+    pub answer: String,
+}
+
+fn default_problem_type() -> String
+{
+    "standard".to_string()
+}
+
+fn default_misc() -> serde_json::Value
+{
+    serde_json::json!({})
+}
+
+//uploaded test data of problem `id`
+pub fn problem_dir(config: &Config, id: usize) -> String
+{
+    format!("{}/problems/{}", config.server.data_dir.trim_end_matches('/'), id)
+}
+
+//put the config problems in the problems table
+//a stored problem is only overwritten when its definition in the config changed since `previous`,
+//so edits made through the api survive restarts and reloads of an unchanged config
+pub async fn import_config_problems(config: &Config, previous: Option<&Config>)
+{
+    let mut lock = PROBLEM_LIST.lock().await;
+    for problem in config.problems.iter()
+    {
+        let changed = match previous
+        {
+            Some(previous) => !previous.problems.contains(problem),
+            None => false,
+        };
+        let saved = match lock.iter_mut().find(|x| x.id == problem.id)
+        {
+            Some(old) if changed =>
+            {
+                *old = problem.clone();
+                storage().update_problem(problem)
+            }
+            Some(_) => Ok(()),
+            None =>
+            {
+                lock.push(problem.clone());
+                storage().insert_problem(problem)
+            }
+        };
+        if saved.is_err()
+        {
+            log::error!("Failed to import problem {}", problem.id);
+        }
+    }
+    lock.sort_by_key(|x| x.id);
+}
+
+//hidden cases are open to everyone while authentication is off
+fn can_edit(config: &Config, identity: Option<web::ReqData<Identity>>) -> bool
+{
+    match identity
+    {
+        Some(identity) => identity.role == Role::Admin || identity.role == Role::ProblemSetter,
+        None => config.server.auth.is_none(),
+    }
+}
+
+//false if a case file under the data directory `dir` of a problem resolves to a file outside it
+//files named anywhere else come from the config, which is trusted
+pub async fn in_problem_dir(dir: &str, file: &str) -> bool
+{
+    if !file.starts_with(&(dir.to_string() + "/"))
+    {
+        return true;
+    }
+    match (canonicalize(dir).await, canonicalize(file).await)
+    {
+        (Ok(dir), Ok(file)) => file.starts_with(dir),
+        //a missing file fails once it is read
+        _ => true,
+    }
+}
+
+async fn read_sample(dir: &str, file: &str) -> String
+{
+    if !in_problem_dir(dir, file).await
+    {
+        log::warn!("Sample {} is outside the problem data", file);
+        return String::new();
+    }
+    match read_to_string(file).await
+    {
+        Ok(data) => data,
+        Err(err) =>
+        {
+            log::warn!("Failed to read sample {}: {}", file, err);
+            String::new()
+        }
+    }
+}
+
+async fn problem_view(config: &Config, problem: Problem, full: bool) -> ProblemView
+{
+    let dir = problem_dir(config, problem.id);
+    let mut samples: Vec<Sample> = Vec::new();
+    for case in problem.cases.iter().filter(|x| x.sample)
+    {
+        samples.push(Sample {
+            input: read_sample(&dir, &case.input_file).await,
+            answer: read_sample(&dir, &case.answer_file).await,
+        });
+    }
+    ProblemView {
+        id: problem.id,
+        name: problem.name,
+        problem_type: problem.problem_type,
+        statement: problem.statement,
+        input_format: problem.input_format,
+        output_format: problem.output_format,
+        limits: ProblemLimits {
+            time_limit: problem.cases.iter().map(|x| x.time_limit).max().unwrap_or(0),
+            memory_limit: problem.cases.iter().map(|x| x.memory_limit).max().unwrap_or(0),
+        },
+        samples,
+        misc: if full { Some(problem.misc) } else { None },
+        cases: if full { Some(problem.cases) } else { None },
+    }
+}
+
+//case files under the data directory of the problem, None if a name tries to leave it
+fn resolve_cases(dir: &str, cases: &[Case]) -> Option<Vec<Case>>
+{
+    let resolve = |name: &str| -> Option<String> {
+        //names from an earlier GET already point into the directory
+        let name = name.strip_prefix(&(dir.to_string() + "/")).unwrap_or(name);
+        let path = Path::new(name);
+        if name.is_empty() || !path.components().all(|x| matches!(x, Component::Normal(_) | Component::CurDir))
+        {
+            return None;
+        }
+        Some(dir.to_string() + "/" + name)
+    };
+    cases.iter()
+        .map(|case| Some(Case {
+            input_file: resolve(&case.input_file)?,
+            answer_file: resolve(&case.answer_file)?,
+            ..case.clone()
+        }))
+        .collect()
+}
+
+fn invalid_cases() -> HttpResponse
+{
+    HttpResponse::BadRequest()
+        .content_type("application/json")
+        .json(HttpError {
+            code: 1,
+            reason: "ERR_INVALID_ARGUMENT".to_string(),
+            message: "Case files must be relative paths inside the problem data.".to_string(),
+        })
+}
+
+//the same errors the config validator reports, warnings are left to the setter
+fn check_posted(problem: &Problem) -> Option<HttpResponse>
+{
+    let errors: Vec<String> = validate::check_posted_problem(problem).iter()
+        .filter(|x| x.severity == Severity::Error)
+        .map(|x| x.location() + ": " + &x.message)
+        .collect();
+    if errors.is_empty()
+    {
+        return None;
+    }
+    Some(HttpResponse::BadRequest()
+        .content_type("application/json")
+        .json(HttpError {
+            code: 1,
+            reason: "ERR_INVALID_ARGUMENT".to_string(),
+            message: "Invalid problem: ".to_string() + &errors.join("; ") + ".",
+        }))
+}
+
+fn problem_not_found(id: usize) -> HttpResponse
+{
+    HttpResponse::NotFound()
+        .content_type("application/json")
+        .json(HttpError {
+            code: 3,
+            reason: "ERR_NOT_FOUND".to_string(),
+            message: "Problem ".to_string() + &id.to_string() + " not found.",
+        })
+}
+
+fn sql_error() -> HttpResponse
+{
+    HttpResponse::InternalServerError()
+        .content_type("application/json")
+        .json(HttpError {
+            code: 5,
+            reason: "ERR_EXTERNAL".to_string(),
+            message: "SQL error".to_string(),
+        })
+}
+
+#[post("/problems")]
+pub async fn post_problems(post_problem: web::Json<PostProblem>, config: web::Data<SharedConfig>) -> HttpResponse
+{
+    let config = config.get();
+    let mut lock = PROBLEM_LIST.lock().await;
+    let id = match post_problem.id
+    {
+        Some(id) if lock.iter().any(|x| x.id == id) =>
+        {
+            return HttpResponse::BadRequest()
+                .content_type("application/json")
+                .json(HttpError {
+                    code: 1,
+                    reason: "ERR_INVALID_ARGUMENT".to_string(),
+                    message: "Problem ".to_string() + &id.to_string() + " already exists.",
+                });
+        }
+        Some(id) => id,
+        None => lock.iter().map(|x| x.id + 1).max().unwrap_or(0),
+    };
+    let cases = match resolve_cases(&problem_dir(&config, id), &post_problem.cases)
+    {
+        Some(cases) => cases,
+        None => return invalid_cases(),
+    };
+    let problem = Problem {
+        id,
+        name: post_problem.name.clone(),
+        problem_type: post_problem.problem_type.clone(),
+        misc: post_problem.misc.clone(),
+        statement: post_problem.statement.clone(),
+        input_format: post_problem.input_format.clone(),
+        output_format: post_problem.output_format.clone(),
+        cases,
+    };
+    if let Some(response) = check_posted(&problem)
+    {
+        return response;
+    }
+    if storage().insert_problem(&problem).is_err()
+    {
+        return sql_error();
+    }
+    lock.push(problem.clone());
+    lock.sort_by_key(|x| x.id);
+
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .json(problem)
+}
+
+#[get("/problems")]
+pub async fn get_problems(config: web::Data<SharedConfig>, identity: Option<web::ReqData<Identity>>) -> HttpResponse
+{
+    let config = config.get();
+    let full = can_edit(&config, identity);
+    let problems = PROBLEM_LIST.lock().await.clone();
+    let mut views: Vec<ProblemView> = Vec::new();
+    for problem in problems
+    {
+        views.push(problem_view(&config, problem, full).await);
+    }
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .json(views)
+}
+
+#[get("/problems/{problemid}")]
+pub async fn get_problems_id(problem_id: web::Path<usize>, config: web::Data<SharedConfig>, identity: Option<web::ReqData<Identity>>) -> HttpResponse
+{
+    let config = config.get();
+    let problem = PROBLEM_LIST.lock().await.iter().find(|x| x.id == *problem_id).cloned();
+    match problem
+    {
+        Some(problem) => HttpResponse::Ok()
+            .content_type("application/json")
+            .json(problem_view(&config, problem, can_edit(&config, identity)).await),
+        None => problem_not_found(*problem_id),
+    }
+}
+
+//replace the metadata of a problem, its id stays
+#[put("/problems/{problemid}")]
+pub async fn put_problems_id(problem_id: web::Path<usize>, post_problem: web::Json<PostProblem>, config: web::Data<SharedConfig>) -> HttpResponse
+{
+    let config = config.get();
+    let id = *problem_id;
+    let mut lock = PROBLEM_LIST.lock().await;
+    let problem = match lock.iter_mut().find(|x| x.id == id)
+    {
+        Some(problem) => problem,
+        None => return problem_not_found(id),
+    };
+    let cases = match resolve_cases(&problem_dir(&config, id), &post_problem.cases)
+    {
+        Some(cases) => cases,
+        None => return invalid_cases(),
+    };
+    let new_problem = Problem {
+        id,
+        name: post_problem.name.clone(),
+        problem_type: post_problem.problem_type.clone(),
+        misc: post_problem.misc.clone(),
+        statement: post_problem.statement.clone(),
+        input_format: post_problem.input_format.clone(),
+        output_format: post_problem.output_format.clone(),
+        cases,
+    };
+    if let Some(response) = check_posted(&new_problem)
+    {
+        return response;
+    }
+    if storage().update_problem(&new_problem).is_err()
+    {
+        return sql_error();
+    }
+    *problem = new_problem.clone();
+
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .json(new_problem)
+}
+
+//a problem can only go while no job or contest uses it
+#[delete("/problems/{problemid}")]
+pub async fn delete_problems_id(problem_id: web::Path<usize>, config: web::Data<SharedConfig>) -> HttpResponse
+{
+    let config = config.get();
+    let id = *problem_id;
+    let mut lock = PROBLEM_LIST.lock().await;
+    let index = match lock.iter().position(|x| x.id == id)
+    {
+        Some(index) => index,
+        None => return problem_not_found(id),
+    };
+    let jobs = match storage().count_jobs(&JobFilter { problem_id: Some(id), ..Default::default() })
+    {
+        Ok(jobs) => jobs,
+        Err(_) => return sql_error(),
+    };
+    let used = jobs > 0
+        || CONTEST_LIST.lock().await.iter().any(|x| x.problem_ids.contains(&id));
+    if used
+    {
+        return HttpResponse::BadRequest()
+            .content_type("application/json")
+            .json(HttpError {
+                code: 2,
+                reason: "ERR_INVALID_STATE".to_string(),
+                message: "Problem ".to_string() + &id.to_string() + " is used by jobs or contests.",
+            });
+    }
+    if storage().delete_problem(id).is_err()
+    {
+        return sql_error();
+    }
+    let problem = lock.remove(index);
+    let _ = remove_dir_all(problem_dir(&config, id)).await;
+
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .json(problem)
+}
+
+//unpack a zip, tar or tar.gz archive into `dir`
+//entries may not leave it and only regular files and directories are taken,
+//a symlink would let the data of a problem show any file of the host
+fn unpack_archive(archive: &[u8], dir: &Path) -> std::io::Result<()>
+{
+    let refuse = |name: &str, reason: &str| Error::new(ErrorKind::InvalidData, name.to_string() + " " + reason + ".");
+    std::fs::create_dir_all(dir)?;
+    if archive.starts_with(b"PK\x03\x04") || archive.starts_with(b"PK\x05\x06")
+    {
+        let mut zip = zip::ZipArchive::new(Cursor::new(archive))?;
+        for index in 0..zip.len()
+        {
+            let mut entry = zip.by_index(index)?;
+            let path = match entry.enclosed_name()
+            {
+                Some(name) => dir.join(name),
+                None => return Err(refuse(entry.name(), "leaves the archive")),
+            };
+            if entry.is_dir()
+            {
+                std::fs::create_dir_all(&path)?;
+            }
+            else if entry.is_file()
+            {
+                if let Some(parent) = path.parent()
+                {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::io::copy(&mut entry, &mut std::fs::File::create(&path)?)?;
+            }
+            else
+            {
+                return Err(refuse(entry.name(), "is not a regular file or directory"));
+            }
+        }
+        return Ok(());
+    }
+
+    let reader: Box<dyn Read + '_> = if archive.starts_with(&[0x1f, 0x8b]) { Box::new(GzDecoder::new(archive)) } else { Box::new(archive) };
+    let mut tar = tar::Archive::new(reader);
+    for entry in tar.entries()?
+    {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().to_string();
+        let kind = entry.header().entry_type();
+        if !kind.is_file() && !kind.is_dir()
+        {
+            return Err(refuse(&name, "is not a regular file or directory"));
+        }
+        if !entry.unpack_in(dir)?
+        {
+            return Err(refuse(&name, "leaves the archive"));
+        }
+    }
+    Ok(())
+}
+
+fn save_failed(id: usize, err: Error) -> HttpResponse
+{
+    log::error!("Failed to move test data of problem {}: {}", id, err);
+    HttpResponse::InternalServerError()
+        .content_type("application/json")
+        .json(HttpError {
+            code: 5,
+            reason: "ERR_EXTERNAL".to_string(),
+            message: "Failed to save test data.".to_string(),
+        })
+}
+
+//replace the test data of a problem with the archive in the body
+//the old data stays if the archive is broken or misses a case file
+#[put("/problems/{problemid}/data")]
+pub async fn put_problems_data(problem_id: web::Path<usize>, body: web::Bytes, config: web::Data<SharedConfig>) -> HttpResponse
+{
+    let config = config.get();
+    let id = *problem_id;
+    let problem = match PROBLEM_LIST.lock().await.iter().find(|x| x.id == id)
+    {
+        Some(problem) => problem.clone(),
+        None => return problem_not_found(id),
+    };
+
+    let dir = problem_dir(&config, id);
+    let staging = format!("{}.upload-{}", dir, Utc::now().timestamp_micros());
+    let target = staging.clone();
+    let unpacked = tokio::task::spawn_blocking(move || unpack_archive(&body, Path::new(&target))).await.unwrap();
+    if let Err(err) = unpacked
+    {
+        let _ = remove_dir_all(&staging).await;
+        return HttpResponse::BadRequest()
+            .content_type("application/json")
+            .json(HttpError {
+                code: 1,
+                reason: "ERR_INVALID_ARGUMENT".to_string(),
+                message: "Invalid archive: ".to_string() + &err.to_string(),
+            });
+    }
+
+    let missing: Vec<&str> = problem.cases.iter()
+        .flat_map(|x| [x.input_file.as_str(), x.answer_file.as_str()])
+        .filter_map(|x| x.strip_prefix(&(dir.clone() + "/")))
+        .filter(|x| !Path::new(&staging).join(x).is_file())
+        .collect();
+    if !missing.is_empty()
+    {
+        let _ = remove_dir_all(&staging).await;
+        return HttpResponse::BadRequest()
+            .content_type("application/json")
+            .json(HttpError {
+                code: 1,
+                reason: "ERR_INVALID_ARGUMENT".to_string(),
+                message: "Archive misses ".to_string() + &missing.join(", ") + ".",
+            });
+    }
+
+    //move the old data aside, swap the new data in and only then delete the old data,
+    //so it can be put back if the swap fails
+    let old = format!("{}.old-{}", dir, Utc::now().timestamp_micros());
+    let had_data = match rename(&dir, &old).await
+    {
+        Ok(()) => true,
+        Err(err) if err.kind() == ErrorKind::NotFound => false,
+        Err(err) =>
+        {
+            let _ = remove_dir_all(&staging).await;
+            return save_failed(id, err);
+        }
+    };
+    if let Err(err) = rename(&staging, &dir).await
+    {
+        if had_data
+        {
+            if let Err(err) = rename(&old, &dir).await
+            {
+                log::error!("Failed to restore test data of problem {} from {}: {}", id, old, err);
+            }
+        }
+        let _ = remove_dir_all(&staging).await;
+        return save_failed(id, err);
+    }
+    if had_data
+    {
+        let _ = remove_dir_all(&old).await;
+    }
+
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .json(problem)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    fn tar_with(kind: tar::EntryType, link: Option<&str>) -> Vec<u8>
+    {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(kind);
+        header.set_mode(0o644);
+        header.set_size(0);
+        if let Some(link) = link
+        {
+            header.set_link_name(link).unwrap();
+        }
+        header.set_cksum();
+        builder.append_data(&mut header, "1.in", std::io::empty()).unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    fn zip_with(add: impl FnOnce(&mut zip::ZipWriter<Cursor<Vec<u8>>>)) -> Vec<u8>
+    {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        add(&mut zip);
+        zip.finish().unwrap().into_inner()
+    }
+
+    //an empty directory for one test
+    fn scratch(test: &str) -> PathBuf
+    {
+        let dir = std::env::temp_dir().join(format!("oj-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn regular_files_are_unpacked()
+    {
+        let dir = scratch("tar-file");
+        unpack_archive(&tar_with(tar::EntryType::Regular, None), &dir).unwrap();
+        assert!(dir.join("1.in").is_file());
+
+        let dir = scratch("zip-file");
+        let archive = zip_with(|zip| {
+            zip.start_file("cases/1.ans", zip::write::SimpleFileOptions::default()).unwrap();
+            zip.write_all(b"3\n").unwrap();
+        });
+        unpack_archive(&archive, &dir).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("cases/1.ans")).unwrap(), "3\n");
+    }
+
+    #[test]
+    fn links_are_refused()
+    {
+        for (test, kind) in [("tar-symlink", tar::EntryType::Symlink), ("tar-hardlink", tar::EntryType::Link)]
+        {
+            let err = unpack_archive(&tar_with(kind, Some("/etc/passwd")), &scratch(test)).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+        let archive = zip_with(|zip| zip.add_symlink("1.in", "/etc/passwd", zip::write::SimpleFileOptions::default()).unwrap());
+        let err = unpack_archive(&archive, &scratch("zip-symlink")).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::HashSet;
+use actix_web::{get, post, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use chrono::Utc;
+
+use crate::globals::{JOB_CACHE, JOB_QUEUE};
+use crate::queue::Priority;
+use crate::api::error::HttpError;
+use crate::api::events::publish_job;
+use crate::api::job::{sql_error, Job};
+use crate::api::state::{JobResult, JobState};
+use crate::storage::{storage, JobFilter, JobPage};
+
+//finished jobs requeued together, with their results before and after
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Rejudge
+{
+    pub id: usize,
+    pub created_time: String,
+    pub jobs: Vec<RejudgeJob>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RejudgeJob
+{
+    pub job_id: usize,
+    pub old_result: JobResult,
+    pub old_score: f64,
+    //None until the job is finished again
+    pub new_result: Option<JobResult>,
+    pub new_score: Option<f64>,
+}
+
+impl RejudgeJob
+{
+    fn changed(&self) -> bool
+    {
+        self.new_result.is_some_and(|x| x != self.old_result) || self.new_score.is_some_and(|x| x != self.old_score)
+    }
+}
+
+#[derive(Serialize)]
+struct RejudgeProgress
+{
+    id: usize,
+    created_time: String,
+    total: usize,
+    finished: usize,
+    //finished with another result or score than before
+    changed: usize,
+    jobs: Vec<RejudgeJob>,
+}
+
+impl From<Rejudge> for RejudgeProgress
+{
+    fn from(rejudge: Rejudge) -> RejudgeProgress
+    {
+        RejudgeProgress {
+            id: rejudge.id,
+            created_time: rejudge.created_time,
+            total: rejudge.jobs.len(),
+            finished: rejudge.jobs.iter().filter(|x| x.new_result.is_some()).count(),
+            changed: rejudge.jobs.iter().filter(|x| x.changed()).count(),
+            jobs: rejudge.jobs,
+        }
+    }
+}
+
+//the filters of GET /jobs that make sense for picking finished jobs
+#[derive(Deserialize)]
+pub struct PostRejudge
+{
+    user_id: Option<usize>,
+    contest_id: Option<usize>,
+    problem_id: Option<usize>,
+    language: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    result: Option<String>,
+}
+
+//requeue all finished jobs matching the filters, after new submissions
+#[post("/jobs/rejudge")]
+pub async fn post_jobs_rejudge(post_rejudge: web::Json<PostRejudge>) -> HttpResponse
+{
+    let post_rejudge = post_rejudge.into_inner();
+    let result = match post_rejudge.result.as_deref().map(|x| x.parse::<JobResult>()).transpose()
+    {
+        Ok(result) => result,
+        Err(message) =>
+        {
+            return HttpResponse::BadRequest()
+                .content_type("application/json")
+                .json(HttpError {
+                    code: 1,
+                    reason: "ERR_INVALID_ARGUMENT".to_string(),
+                    message,
+                });
+        }
+    };
+    let filter = JobFilter {
+        user_id: post_rejudge.user_id,
+        contest_id: post_rejudge.contest_id,
+        problem_id: post_rejudge.problem_id,
+        language: post_rejudge.language,
+        from: post_rejudge.from,
+        to: post_rejudge.to,
+        state: None,
+        result,
+        ..Default::default()
+    };
+    //an empty body would rejudge everything, which is never meant
+    if filter.user_id.is_none() && filter.contest_id.is_none() && filter.problem_id.is_none() && filter.language.is_none()
+        && filter.from.is_none() && filter.to.is_none() && filter.result.is_none()
+    {
+        return HttpResponse::BadRequest()
+            .content_type("application/json")
+            .json(HttpError {
+                code: 1,
+                reason: "ERR_INVALID_ARGUMENT".to_string(),
+                message: "Give at least one filter".to_string(),
+            });
+    }
+    let filter = JobFilter { state: Some(JobState::Finished), ..filter };
+
+    //read without the cache lock, the storage requeues only the jobs still finished
+    //the source code is not needed, workers read the job again when they claim it
+    let page = JobPage { skip_source: true, ..Default::default() };
+    let jobs = match storage().query_jobs(&filter, &page)
+    {
+        Ok(jobs) => jobs,
+        Err(_) => return sql_error(),
+    };
+    let mut requeued = Vec::new();
+    for job in jobs
+    {
+        let case_num = job.case_num().await;
+        requeued.push(Job::from(job, case_num));
+    }
+    let created_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
+
+    //no job is claimed, finished or rejudged alone while the batch is stored
+    let mut cache = JOB_CACHE.lock().await;
+    let rejudge = match storage().next_rejudge_id().and_then(|id| storage().insert_rejudge(id, &created_time, &requeued))
+    {
+        Ok(rejudge) => rejudge,
+        Err(_) => return sql_error(),
+    };
+    let ids: HashSet<usize> = rejudge.jobs.iter().map(|x| x.job_id).collect();
+    requeued.retain(|x| ids.contains(&x.id));
+    //none of them should be cached as finished jobs, drop any stale copy all the same
+    for job in requeued.iter()
+    {
+        cache.remove(job.id);
+    }
+    drop(cache);
+    for job in requeued.iter()
+    {
+        JOB_QUEUE.push(job.id, Priority::Low).await;
+        publish_job(job);
+    }
+    log::info!("Rejudge {} queued {} jobs", rejudge.id, rejudge.jobs.len());
+
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .json(RejudgeProgress::from(rejudge))
+}
+
+#[get("/jobs/rejudge/{rejudgeid}")]
+pub async fn get_jobs_rejudge_id(rejudge_id: web::Path<usize>) -> HttpResponse
+{
+    match storage().get_rejudge(*rejudge_id)
+    {
+        Ok(Some(rejudge)) => HttpResponse::Ok()
+            .content_type("application/json")
+            .json(RejudgeProgress::from(rejudge)),
+        Ok(None) => HttpResponse::NotFound()
+            .content_type("application/json")
+            .json(HttpError {
+                code: 3,
+                reason: "ERR_NOT_FOUND".to_string(),
+                message: "Rejudge ".to_string() + &rejudge_id.to_string() + " not found."
+            }),
+        Err(_) => sql_error(),
+    }
+}
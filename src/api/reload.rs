@@ -0,0 +1,79 @@
+use actix_web::{post, web, HttpResponse};
+use serde::Serialize;
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::arg::SharedConfig;
+use crate::api::auth::init_root_password;
+use crate::api::error::HttpError;
+use crate::api::problem::import_config_problems;
+
+#[derive(Serialize)]
+pub struct Reloaded
+{
+    problems: usize,
+    languages: usize,
+}
+
+//read the config file again and use it for jobs claimed from now on
+//the running config stays if the file is broken
+pub async fn reload_config(config: &SharedConfig) -> std::io::Result<Reloaded>
+{
+    let new_config = config.load()?;
+    let old_config = config.get();
+    if new_config.server.bind_address != old_config.server.bind_address || new_config.server.bind_port != old_config.server.bind_port
+    {
+        log::warn!("The bind address only changes on restart");
+    }
+    if new_config.server.judge_workers != old_config.server.judge_workers
+    {
+        log::warn!("The number of judge workers only changes on restart");
+    }
+    import_config_problems(&new_config, Some(&old_config)).await;
+    init_root_password(&new_config).await;
+    let reloaded = Reloaded {
+        problems: new_config.problems.len(),
+        languages: new_config.languages.len(),
+    };
+    config.set(new_config);
+    log::info!("Config reloaded");
+    Ok(reloaded)
+}
+
+//reload on SIGHUP
+pub async fn reload_on_hangup(config: web::Data<SharedConfig>)
+{
+    let mut hangup = match signal(SignalKind::hangup())
+    {
+        Ok(hangup) => hangup,
+        Err(err) =>
+        {
+            log::error!("Failed to listen for SIGHUP: {}", err);
+            return;
+        }
+    };
+    while hangup.recv().await.is_some()
+    {
+        if let Err(err) = reload_config(&config).await
+        {
+            log::error!("Failed to reload config: {}", err);
+        }
+    }
+}
+
+#[post("/internal/reload")]
+pub async fn post_reload(config: web::Data<SharedConfig>) -> HttpResponse
+{
+    match reload_config(&config).await
+    {
+        Ok(reloaded) => HttpResponse::Ok()
+            .content_type("application/json")
+            .json(reloaded),
+        Err(err) => HttpResponse::InternalServerError()
+            .content_type("application/json")
+            .json(HttpError {
+                code: 5,
+                reason: "ERR_EXTERNAL".to_string(),
+                message: "Failed to reload config: ".to_string() + &err.to_string(),
+            }),
+    }
+}
@@ -0,0 +1,217 @@
+use std::sync::Arc;
+use actix_web::{get, rt, web, HttpRequest, HttpResponse};
+use actix_ws::{Message, MessageStream, Session};
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::{RecvError, TryRecvError}};
+use tokio::time;
+
+use crate::globals::{CONTEST_LIST, JOB_EVENTS, SCOREBOARDS};
+use crate::api::contest::{build_ranklist, ranklist_error, RankQuery, UserRank};
+use crate::api::error::HttpError;
+use crate::api::job::Job;
+use crate::api::state::JobState;
+
+//milliseconds that finished jobs are gathered before the ranklist is rebuilt for them
+const REBUILD_DELAY: u64 = 500;
+
+//contest id, scoring rule and tie breaker
+pub type ScoreboardKey = (usize, Option<String>, Option<String>);
+pub type RanklistSender = broadcast::Sender<Arc<Vec<UserRank>>>;
+
+//messages sent to scoreboard clients
+//the first one is the full ranklist, later ones only carry the rows that changed,
+//keyed by user id, and the ids of users that left the ranklist
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ScoreboardMessage<'a>
+{
+    Full
+    {
+        ranklist: &'a [UserRank],
+    },
+    Diff
+    {
+        changed: Vec<&'a UserRank>,
+        removed: Vec<usize>,
+    },
+}
+
+//live ranklist of a contest over a WebSocket, pushed whenever one of its jobs finishes
+//takes the same query as the ranklist
+#[get("/contests/{contestid}/ranklist/ws")]
+pub async fn get_contests_ranklist_ws(req: HttpRequest, body: web::Payload, get_contest: web::Path<usize>,
+    query: web::Query<RankQuery>) -> actix_web::Result<HttpResponse>
+{
+    let id = *get_contest;
+    //subscribe before building the first ranklist so no rebuild falls in between
+    let ranklists = subscribe(id, &query).await;
+    let ranklist = match build_ranklist(id, &query).await
+    {
+        Ok(Some(ranklist)) => ranklist,
+        Err(_) => return Ok(ranklist_error()),
+        Ok(None) =>
+        {
+            return Ok(HttpResponse::NotFound()
+                .content_type("application/json")
+                .json(HttpError {
+                    code: 3,
+                    reason: "ERR_NOT_FOUND".to_string(),
+                    message: "Contest ".to_string() + &id.to_string() + " not found.",
+                }));
+        }
+    };
+
+    let (response, session, messages) = actix_ws::handle(&req, body)?;
+    rt::spawn(push_ranklist(Arc::new(ranklist), ranklists, session, messages));
+    Ok(response)
+}
+
+//the shared ranklists of a contest and query, starting their rebuilding if nobody watches them yet
+async fn subscribe(id: usize, query: &RankQuery) -> broadcast::Receiver<Arc<Vec<UserRank>>>
+{
+    let key = (id, query.scoring_rule.clone(), query.tie_breaker.clone());
+    let mut lock = SCOREBOARDS.lock().await;
+    if let Some(tx) = lock.get(&key)
+    {
+        return tx.subscribe();
+    }
+    let (tx, rx) = broadcast::channel(16);
+    lock.insert(key.clone(), tx.clone());
+    rt::spawn(rebuild_ranklists(key, tx));
+    rx
+}
+
+//rebuild the ranklist once for each burst of finished jobs of the contest, for all of its clients
+//ends when the last client is gone
+async fn rebuild_ranklists(key: ScoreboardKey, tx: RanklistSender)
+{
+    let query = RankQuery {
+        scoring_rule: key.1.clone(),
+        tie_breaker: key.2.clone(),
+    };
+    let mut rx = JOB_EVENTS.subscribe();
+    loop
+    {
+        let changed = match rx.recv().await
+        {
+            Ok(job) => job.state == JobState::Finished && in_contest(key.0, &job).await,
+            //some updates were missed, refresh to be safe
+            Err(RecvError::Lagged(_)) => true,
+            Err(RecvError::Closed) => break,
+        };
+        //clients subscribe under the same lock, so none can join a sender that is going away
+        if tx.receiver_count() == 0
+        {
+            let mut lock = SCOREBOARDS.lock().await;
+            if tx.receiver_count() == 0
+            {
+                lock.remove(&key);
+                return;
+            }
+        }
+        if !changed
+        {
+            continue;
+        }
+        time::sleep(time::Duration::from_millis(REBUILD_DELAY)).await;
+        //whatever came in meanwhile is covered by the same rebuild
+        while !matches!(rx.try_recv(), Err(TryRecvError::Empty) | Err(TryRecvError::Closed)) {}
+        match build_ranklist(key.0, &query).await
+        {
+            Ok(Some(ranklist)) =>
+            {
+                let _ = tx.send(Arc::new(ranklist));
+            }
+            //the clients keep the last ranklist until the next finished job
+            Err(_) => {}
+            //the contest is gone
+            Ok(None) => break,
+        }
+    }
+    //dropping the sender closes the clients
+    SCOREBOARDS.lock().await.remove(&key);
+}
+
+async fn push_ranklist(mut ranklist: Arc<Vec<UserRank>>, mut ranklists: broadcast::Receiver<Arc<Vec<UserRank>>>,
+    mut session: Session, mut messages: MessageStream)
+{
+    if send(&mut session, &ScoreboardMessage::Full { ranklist: &ranklist }).await.is_err()
+    {
+        return;
+    }
+    loop
+    {
+        tokio::select!
+        {
+            message = messages.recv() =>
+            {
+                match message
+                {
+                    Some(Ok(Message::Ping(bytes))) =>
+                    {
+                        if session.pong(&bytes).await.is_err()
+                        {
+                            return;
+                        }
+                    }
+                    Some(Ok(Message::Close(reason))) =>
+                    {
+                        let _ = session.close(reason).await;
+                        return;
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(_)) | None => return,
+                }
+            }
+            new_ranklist = ranklists.recv() =>
+            {
+                let new_ranklist = match new_ranklist
+                {
+                    Ok(new_ranklist) => new_ranklist,
+                    //only the latest ranklist matters
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return,
+                };
+                let message = ScoreboardMessage::Diff {
+                    changed: new_ranklist.iter()
+                        .filter(|x| !ranklist.iter().any(|y| y.user.id == x.user.id && y.rank == x.rank && y.scores == x.scores))
+                        .collect(),
+                    removed: ranklist.iter()
+                        .filter(|x| !new_ranklist.iter().any(|y| y.user.id == x.user.id))
+                        .map(|x| x.user.id)
+                        .collect(),
+                };
+                let sent = match &message
+                {
+                    ScoreboardMessage::Diff { changed, removed } if changed.is_empty() && removed.is_empty() => Ok(()),
+                    _ => send(&mut session, &message).await,
+                };
+                if sent.is_err()
+                {
+                    return;
+                }
+                ranklist = new_ranklist;
+            }
+        }
+    }
+}
+
+async fn send(session: &mut Session, message: &ScoreboardMessage<'_>) -> Result<(), actix_ws::Closed>
+{
+    session.text(serde_json::to_string(message).unwrap()).await
+}
+
+//whether a job counts in the ranklist of contest `id`
+async fn in_contest(id: usize, job: &Job) -> bool
+{
+    if id == 0
+    {
+        return true;
+    }
+    match CONTEST_LIST.lock().await.get(id - 1)
+    {
+        Some(contest) => contest.user_ids.contains(&job.submission.user_id)
+            && contest.problem_ids.contains(&job.submission.problem_id),
+        None => false,
+    }
+}
@@ -0,0 +1,183 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+
+//where a job is in its life, see can_become for the legal moves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum JobState
+{
+    Queueing,
+    Running,
+    Finished,
+    Canceled,
+}
+
+impl JobState
+{
+    pub const ALL: [JobState; 4] = [JobState::Queueing, JobState::Running, JobState::Finished, JobState::Canceled];
+
+    pub fn as_str(&self) -> &'static str
+    {
+        match self
+        {
+            JobState::Queueing => "Queueing",
+            JobState::Running => "Running",
+            JobState::Finished => "Finished",
+            JobState::Canceled => "Canceled",
+        }
+    }
+
+    //Queueing -> Running -> Finished, Queueing -> Canceled,
+    //and back to Queueing from Finished or Canceled on rejudge
+    pub fn can_become(&self, next: JobState) -> bool
+    {
+        matches!((self, next),
+            (JobState::Queueing, JobState::Running)
+            | (JobState::Queueing, JobState::Canceled)
+            | (JobState::Running, JobState::Finished)
+            | (JobState::Finished, JobState::Queueing)
+            | (JobState::Canceled, JobState::Queueing))
+    }
+}
+
+//result of a job or of one of its cases
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum JobResult
+{
+    Waiting,
+    Running,
+    Accepted,
+    CompilationError,
+    CompilationSuccess,
+    CompilationTimeout,
+    WrongAnswer,
+    RuntimeError,
+    TimeLimitExceeded,
+    MemoryLimitExceeded,
+    SystemError,
+    SpjError,
+    Skipped,
+}
+
+impl JobResult
+{
+    pub const ALL: [JobResult; 13] = [
+        JobResult::Waiting, JobResult::Running, JobResult::Accepted,
+        JobResult::CompilationError, JobResult::CompilationSuccess, JobResult::CompilationTimeout,
+        JobResult::WrongAnswer, JobResult::RuntimeError, JobResult::TimeLimitExceeded,
+        JobResult::MemoryLimitExceeded, JobResult::SystemError, JobResult::SpjError, JobResult::Skipped,
+    ];
+
+    pub fn as_str(&self) -> &'static str
+    {
+        match self
+        {
+            JobResult::Waiting => "Waiting",
+            JobResult::Running => "Running",
+            JobResult::Accepted => "Accepted",
+            JobResult::CompilationError => "Compilation Error",
+            JobResult::CompilationSuccess => "Compilation Success",
+            JobResult::CompilationTimeout => "Compilation Timeout",
+            JobResult::WrongAnswer => "Wrong Answer",
+            JobResult::RuntimeError => "Runtime Error",
+            JobResult::TimeLimitExceeded => "Time Limit Exceeded",
+            JobResult::MemoryLimitExceeded => "Memory Limit Exceeded",
+            JobResult::SystemError => "System Error",
+            JobResult::SpjError => "SPJ Error",
+            JobResult::Skipped => "Skipped",
+        }
+    }
+}
+
+//both enums go over the wire as their names above
+macro_rules! wire_string
+{
+    ($name:ident) =>
+    {
+        impl fmt::Display for $name
+        {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+            {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl FromStr for $name
+        {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err>
+            {
+                $name::ALL.iter()
+                    .find(|x| x.as_str() == s)
+                    .copied()
+                    .ok_or_else(|| format!("Unknown {} {}", stringify!($name), s))
+            }
+        }
+
+        impl From<$name> for String
+        {
+            fn from(value: $name) -> String
+            {
+                value.as_str().to_string()
+            }
+        }
+
+        impl TryFrom<String> for $name
+        {
+            type Error = String;
+
+            fn try_from(value: String) -> Result<Self, Self::Error>
+            {
+                value.parse()
+            }
+        }
+    };
+}
+
+wire_string!(JobState);
+wire_string!(JobResult);
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn only_the_listed_moves_are_legal()
+    {
+        let legal = [
+            (JobState::Queueing, JobState::Running),
+            (JobState::Queueing, JobState::Canceled),
+            (JobState::Running, JobState::Finished),
+            (JobState::Finished, JobState::Queueing),
+            (JobState::Canceled, JobState::Queueing),
+        ];
+        for from in JobState::ALL
+        {
+            for to in JobState::ALL
+            {
+                assert_eq!(from.can_become(to), legal.contains(&(from, to)), "{} -> {}", from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn names_go_both_ways()
+    {
+        for state in JobState::ALL
+        {
+            assert_eq!(state.as_str().parse::<JobState>(), Ok(state));
+        }
+        for result in JobResult::ALL
+        {
+            assert_eq!(serde_json::to_value(result).unwrap(), result.as_str());
+            assert_eq!(serde_json::from_value::<JobResult>(result.as_str().into()).unwrap(), result);
+        }
+        assert_eq!(serde_json::to_value(JobResult::CompilationError).unwrap(), "Compilation Error");
+        assert!("Done".parse::<JobState>().is_err());
+        assert!(serde_json::from_value::<JobResult>("Accept".into()).is_err());
+    }
+}
@@ -2,14 +2,55 @@ use actix_web::{get, post, web, HttpResponse};
 use serde::{Deserialize, Serialize};
 
 use crate::globals::USER_LIST;
+use crate::api::auth::hash_password;
 use crate::api::error::HttpError;
-use crate::sql::{insert_user, update_user};
+use crate::storage::storage;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct User
 {
     pub id: usize,
     pub name: String,
+    #[serde(default)]
+    pub role: Role,
+    //pbkdf2 hash, None if the user can not log in
+    #[serde(skip)]
+    pub password_hash: Option<String>,
+}
+
+//what a user may do once authentication is on
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Role
+{
+    Admin,
+    ProblemSetter,
+    #[default]
+    Contestant,
+}
+
+impl Role
+{
+    pub fn as_str(&self) -> &'static str
+    {
+        match self
+        {
+            Role::Admin => "admin",
+            Role::ProblemSetter => "problem_setter",
+            Role::Contestant => "contestant",
+        }
+    }
+
+    //unknown roles get the least rights
+    pub fn parse(role: &str) -> Role
+    {
+        match role
+        {
+            "admin" => Role::Admin,
+            "problem_setter" => Role::ProblemSetter,
+            _ => Role::Contestant,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -17,14 +58,23 @@ pub struct PostUser
 {
     pub id: Option<usize>,
     pub name: String,
+    pub password: Option<String>,
+    pub role: Option<Role>,
 }
 
 #[post("/users")]
 pub async fn post_users(post_user: web::Json<PostUser>) -> HttpResponse
 {
+    //hashing is slow, other requests need the user list meanwhile
+    let password_hash = match &post_user.password
+    {
+        Some(password) => Some(hash_password(password).await),
+        None => None,
+    };
     let mut lock = USER_LIST.lock().await;
     let max = lock.len();
-    if lock.iter().any(|x| x.name == post_user.name)
+    //a user keeps its own name when only the password or role changes
+    if lock.iter().any(|x| x.name == post_user.name && Some(x.id) != post_user.id)
     {
         return HttpResponse::BadRequest()
             .content_type("application/json")
@@ -50,8 +100,16 @@ pub async fn post_users(post_user: web::Json<PostUser>) -> HttpResponse
         else
         {
             lock[id].name = post_user.name.clone();
+            if let Some(role) = post_user.role
+            {
+                lock[id].role = role;
+            }
+            if password_hash.is_some()
+            {
+                lock[id].password_hash = password_hash;
+            }
 
-            if let Err(_) = update_user(&lock[id]).await
+            if storage().update_user(&lock[id]).is_err()
             {
                 return HttpResponse::InternalServerError()
                         .content_type("application/json")
@@ -67,9 +125,14 @@ pub async fn post_users(post_user: web::Json<PostUser>) -> HttpResponse
         }
     }
     //new user
-    let user = User { id: max, name: post_user.name.clone(), };
+    let user = User {
+        id: max,
+        name: post_user.name.clone(),
+        role: post_user.role.unwrap_or_default(),
+        password_hash,
+    };
     lock.push(user.clone());
-    if let Err(_) = insert_user(&user).await
+    if storage().insert_user(&user).is_err()
     {
         return HttpResponse::InternalServerError()
                 .content_type("application/json")
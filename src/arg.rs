@@ -1,10 +1,36 @@
 use clap::{Arg, ArgAction, Command};
 use serde::{Serialize, Deserialize};
-use serde_json;
-use std::{fs::File, io::{BufReader, Result}};
+use serde_json::Value;
+use std::{fs, io::{Error, ErrorKind, Result}, path::Path};
+use std::sync::{Arc, RwLock};
+
+use crate::validate::{self, Severity};
+
+//prefix of environment variables overriding config values, "__" separates the keys
+const ENV_PREFIX: &str = "OJ_";
+//string values that may be missing from the file, overrides of them are never parsed as json
+const STRING_KEYS: &[&str] = &[
+    "server.bind_address", "server.data_dir", "server.storage", "server.database",
+    "server.auth.secret", "server.auth.root_password",
+];
+//how deep problem lists may include each other
+const INCLUDE_DEPTH: usize = 8;
+
+//options from the command line
+pub struct Args
+{
+    pub config: Config,
+    pub config_file: String,
+    //drop all data on startup
+    pub flush: bool,
+    //mark jobs interrupted by a restart as System Error instead of judging them again
+    pub fail_interrupted: bool,
+    //bring the database up to date and exit
+    pub migrate_only: bool,
+}
 
 //read command line
-pub fn get_arg() -> Result<(Config, bool)>
+pub fn get_arg() -> Result<Args>
 {
     let args = Command::new("OJ")
         .arg(Arg::new("config")
@@ -17,12 +43,38 @@ pub fn get_arg() -> Result<(Config, bool)>
             .short('f')
             .long("flush-data")
             .action(ArgAction::SetTrue))
+        .arg(Arg::new("fail-interrupted")
+            .long("fail-interrupted")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("database")
+            .long("database")
+            .value_name("FILE")
+            .help("SQLite database file, overrides server.database"))
+        .arg(Arg::new("migrate-only")
+            .long("migrate-only")
+            .help("Apply pending database migrations and exit")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("check-config")
+            .long("check-config")
+            .help("Report every problem in the config file and exit")
+            .action(ArgAction::SetTrue))
         .get_matches();
 
-    let config = get_config(args.get_one::<String>("config").unwrap())?;
+    let config_file = args.get_one::<String>("config").unwrap().clone();
+    if args.get_flag("check-config")
+    {
+        std::process::exit(check_config(&config_file));
+    }
+    let mut config = get_config(&config_file)?;
+    if let Some(database) = args.get_one::<String>("database")
+    {
+        config.server.database = database.clone();
+    }
     let flush: bool = args.get_flag("flush");
+    let fail_interrupted: bool = args.get_flag("fail-interrupted");
+    let migrate_only: bool = args.get_flag("migrate-only");
 
-    Ok((config, flush))
+    Ok(Args { config, config_file, flush, fail_interrupted, migrate_only })
 }
 
 //struct for config
@@ -32,6 +84,18 @@ pub struct Config
     pub server: Server,
     pub problems: Vec<Problem>,
     pub languages: Vec<Language>,
+    //where each problem was written, empty for configs not read from a file
+    #[serde(skip)]
+    pub problem_sources: Vec<ProblemSource>,
+}
+
+//the file and json path of a problem before the includes were expanded, for diagnostics
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProblemSource
+{
+    //None for the config file itself
+    pub file: Option<String>,
+    pub path: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -39,9 +103,70 @@ pub struct Server
 {
     pub bind_address: String,
     pub bind_port: u16,
+    //wall clock limit of a case as a multiple of its cpu time limit
+    //1.0 keeps the wall clock cap of a case at its time limit, raise it on busy judges
+    //so that waiting for the cpu does not count as exceeding the time limit
+    #[serde(default = "default_wall_time_ratio")]
+    pub wall_time_ratio: f64,
+    //jobs judged at the same time
+    #[serde(default = "default_judge_workers")]
+    pub judge_workers: usize,
+    //without it every endpoint is open and jobs are submitted as PostJob.user_id
+    #[serde(default)]
+    pub auth: Option<Auth>,
+    //where test data uploaded through /problems is kept
+    #[serde(default = "default_data_dir")]
+    pub data_dir: String,
+    //"sqlite" keeps everything in `database`, "memory" forgets it on exit
+    #[serde(default = "default_storage")]
+    pub storage: String,
+    #[serde(default = "default_database")]
+    pub database: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Auth
+{
+    //key signing the bearer tokens
+    pub secret: String,
+    //seconds a token stays valid
+    #[serde(default = "default_token_lifetime")]
+    pub token_lifetime: u64,
+    //password set for root (user 0) while it has none
+    pub root_password: String,
+}
+
+fn default_token_lifetime() -> u64
+{
+    24 * 60 * 60
+}
+
+fn default_storage() -> String
+{
+    "sqlite".to_string()
+}
+
+fn default_database() -> String
+{
+    "database.db".to_string()
+}
+
+fn default_data_dir() -> String
+{
+    "./data".to_string()
+}
+
+fn default_wall_time_ratio() -> f64
+{
+    1.0
+}
+
+fn default_judge_workers() -> usize
+{
+    4
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Problem
 {
     pub id: usize,
@@ -49,10 +174,17 @@ pub struct Problem
     #[serde(rename = "type")]
     pub problem_type: String,
     pub misc: serde_json::Value,
+    //Markdown, LaTeX between $ signs is left for the client to render
+    #[serde(default)]
+    pub statement: String,
+    #[serde(default)]
+    pub input_format: String,
+    #[serde(default)]
+    pub output_format: String,
     pub cases: Vec<Case>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Case
 {
     pub score: f64,
@@ -60,6 +192,9 @@ pub struct Case
     pub answer_file: String,
     pub time_limit: u64,
     pub memory_limit: u64,
+    //samples are shown with the statement, the other cases stay hidden
+    #[serde(default)]
+    pub sample: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -68,13 +203,354 @@ pub struct Language
     pub name: String,
     pub file_name: String,
     pub command: Vec<String>,
+    //run submissions in namespaces with a seccomp allowlist
+    #[serde(default)]
+    pub isolation: bool,
+    //wall clock limit of the compiler, microseconds, 0 means unlimited
+    #[serde(default = "default_compile_time_limit")]
+    pub compile_time_limit: u64,
+    //memory limit of the compiler, bytes, 0 means unlimited
+    //without cgroup v2 this caps the address space, which some compilers reserve generously
+    #[serde(default)]
+    pub compile_memory_limit: u64,
+}
+
+fn default_compile_time_limit() -> u64
+{
+    30_000_000
 }
 
-//gen config from json
+//gen config from a json, yaml or toml file, with its includes and the environment overrides
+fn read_config(file_name: &str) -> Result<Config>
+{
+    let mut value = read_value(file_name)?;
+    let mut sources: Vec<ProblemSource> = Vec::new();
+    if let Some(problems) = value.get_mut("problems")
+    {
+        expand_includes(problems, file_name, None, Some("problems"), 0, &mut sources)?;
+    }
+    apply_env_overrides(&mut value, std::env::vars());
+    let mut config: Config = serde_json::from_value(value).map_err(|err| Error::new(ErrorKind::InvalidData, format!("{}: {}", file_name, err)))?;
+    config.problem_sources = sources;
+    Ok(config)
+}
+
+//the format is told by the extension, json if it is neither yaml nor toml
+fn read_value(file_name: &str) -> Result<Value>
+{
+    let text = fs::read_to_string(file_name).map_err(|err| Error::new(err.kind(), format!("{}: {}", file_name, err)))?;
+    let parsed = match Path::new(file_name).extension().and_then(|x| x.to_str())
+    {
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&text).map_err(|err| err.to_string()),
+        Some("toml") => toml::from_str(&text).map_err(|err| err.to_string()),
+        _ => serde_json::from_str(&text).map_err(|err| err.to_string()),
+    };
+    parsed.map_err(|err| Error::new(ErrorKind::InvalidData, format!("{}: {}", file_name, err)))
+}
+
+//replace {"include": "<file>"} entries of a problem list with the problems in that file,
+//named relative to the including file
+//an included file holds a list of problems, a single problem, or a table with a problems list
+//`sources` gets the file and path of every problem, `list_path` is the path of the list in
+//`source_file`, None when the file is a single problem
+fn expand_includes(problems: &mut Value, file_name: &str, source_file: Option<&str>, list_path: Option<&str>, depth: usize,
+    sources: &mut Vec<ProblemSource>) -> Result<()>
+{
+    let list = match problems.as_array_mut()
+    {
+        Some(list) => list,
+        None => return Ok(()),
+    };
+    let mut expanded: Vec<Value> = Vec::new();
+    for (index, entry) in list.drain(..).enumerate()
+    {
+        let include = match entry.get("include").and_then(|x| x.as_str())
+        {
+            Some(include) => include,
+            None =>
+            {
+                sources.push(ProblemSource {
+                    file: source_file.map(|x| x.to_string()),
+                    path: list_path.map_or(String::new(), |x| format!("{}[{}]", x, index)),
+                });
+                expanded.push(entry);
+                continue;
+            }
+        };
+        if depth >= INCLUDE_DEPTH
+        {
+            return Err(Error::new(ErrorKind::InvalidData, format!("{}: includes nested too deep at {}", file_name, include)));
+        }
+        let path = Path::new(file_name).parent().unwrap_or(Path::new("")).join(include);
+        let path = path.to_string_lossy().to_string();
+        let mut included = read_value(&path)?;
+        let mut included_path = Some("");
+        if let Some(problems) = included.get_mut("problems")
+        {
+            included = problems.take();
+            included_path = Some("problems");
+        }
+        if !included.is_array()
+        {
+            included = Value::Array(vec![included]);
+            included_path = None;
+        }
+        expand_includes(&mut included, &path, Some(&path), included_path, depth + 1, sources)?;
+        if let Value::Array(problems) = included
+        {
+            expanded.extend(problems);
+        }
+    }
+    *list = expanded;
+    Ok(())
+}
+
+//OJ_SERVER__BIND_PORT=8080 sets server.bind_port, numbers index lists as in OJ_LANGUAGES__0__NAME
+//values are read as json when they parse, as strings otherwise
+fn apply_env_overrides(config: &mut Value, vars: impl Iterator<Item = (String, String)>)
+{
+    for (name, value) in vars
+    {
+        let keys: Vec<String> = match name.strip_prefix(ENV_PREFIX)
+        {
+            Some(path) => path.split("__").map(|x| x.to_lowercase()).collect(),
+            None => continue,
+        };
+        match value_at(config, &keys)
+        {
+            Some(target) =>
+            {
+                //"123456" is a fine secret, strings stay strings
+                if target.is_string() || STRING_KEYS.contains(&keys.join(".").as_str())
+                {
+                    *target = Value::String(value);
+                }
+                else
+                {
+                    *target = serde_json::from_str(&value).unwrap_or(Value::String(value));
+                }
+                log::info!("Config {} set from {}", keys.join("."), name);
+            }
+            None => log::warn!("Config override {} points into a missing list item", name),
+        }
+    }
+}
+
+//the value at a key path, missing object keys are created
+fn value_at<'a>(value: &'a mut Value, keys: &[String]) -> Option<&'a mut Value>
+{
+    let (key, rest) = match keys.split_first()
+    {
+        Some(split) => split,
+        None => return Some(value),
+    };
+    let next = match value
+    {
+        Value::Array(list) => list.get_mut(key.parse::<usize>().ok()?)?,
+        _ =>
+        {
+            if !value.is_object()
+            {
+                *value = Value::Object(serde_json::Map::new());
+            }
+            value.as_object_mut()?.entry(key.clone()).or_insert(Value::Null)
+        }
+    };
+    value_at(next, rest)
+}
+
+//read and validate a config, warnings are logged and errors refuse it
 fn get_config(file_name: &str) -> Result<Config>
 {
-    let file = File::open(file_name)?;
-    let reader = BufReader::new(file);
-    let config = serde_json::from_reader(reader)?;
+    let config = read_config(file_name)?;
+    let mut errors: Vec<String> = Vec::new();
+    for diagnostic in validate::check(&config)
+    {
+        match diagnostic.severity
+        {
+            Severity::Warning => log::warn!("{}: {}", file_name, diagnostic),
+            Severity::Error =>
+            {
+                log::error!("{}: {}", file_name, diagnostic);
+                errors.push(diagnostic.location() + ": " + &diagnostic.message);
+            }
+        }
+    }
+    if !errors.is_empty()
+    {
+        return Err(Error::new(ErrorKind::InvalidData, format!("{}: {}", file_name, errors.join("; "))));
+    }
     Ok(config)
-}
\ No newline at end of file
+}
+
+//--check-config, prints every diagnostic and gives the exit code
+fn check_config(file_name: &str) -> i32
+{
+    let config = match read_config(file_name)
+    {
+        Ok(config) => config,
+        Err(err) =>
+        {
+            println!("error: {}", err);
+            return 1;
+        }
+    };
+    let diagnostics = validate::check(&config);
+    for diagnostic in diagnostics.iter()
+    {
+        println!("{}", diagnostic);
+    }
+    let errors = diagnostics.iter().filter(|x| x.severity == Severity::Error).count();
+    println!("{}: {} errors, {} warnings", file_name, errors, diagnostics.len() - errors);
+    if errors > 0 { 1 } else { 0 }
+}
+
+//the config in use, swapped as a whole on reload
+//jobs take a snapshot when they start and finish with it
+pub struct SharedConfig
+{
+    file_name: String,
+    current: RwLock<Arc<Config>>,
+}
+
+impl SharedConfig
+{
+    pub fn new(file_name: String, config: Config) -> SharedConfig
+    {
+        SharedConfig {
+            file_name,
+            current: RwLock::new(Arc::new(config)),
+        }
+    }
+
+    pub fn get(&self) -> Arc<Config>
+    {
+        self.current.read().unwrap().clone()
+    }
+
+    //read and validate the config file again, without using it yet
+    //the storage stays open, so it keeps its settings, which may come from --database
+    pub fn load(&self) -> Result<Config>
+    {
+        let mut config = get_config(&self.file_name)?;
+        let current = self.get();
+        config.server.storage = current.server.storage.clone();
+        config.server.database = current.server.database.clone();
+        Ok(config)
+    }
+
+    pub fn set(&self, config: Config)
+    {
+        *self.current.write().unwrap() = Arc::new(config);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use serde_json::json;
+
+    fn overridden(mut config: Value, vars: &[(&str, &str)]) -> Value
+    {
+        apply_env_overrides(&mut config, vars.iter().map(|(x, y)| (x.to_string(), y.to_string())));
+        config
+    }
+
+    #[test]
+    fn numeric_secrets_stay_strings()
+    {
+        let config = overridden(json!({"server": {}}), &[("OJ_SERVER__AUTH__SECRET", "123456"), ("OJ_SERVER__AUTH__ROOT_PASSWORD", "true")]);
+        assert_eq!(config["server"]["auth"], json!({"secret": "123456", "root_password": "true"}));
+    }
+
+    #[test]
+    fn strings_in_the_file_stay_strings()
+    {
+        let config = overridden(json!({"languages": [{"name": "Rust"}]}), &[("OJ_LANGUAGES__0__NAME", "2021")]);
+        assert_eq!(config["languages"][0]["name"], json!("2021"));
+    }
+
+    #[test]
+    fn other_values_are_parsed()
+    {
+        let config = overridden(json!({"server": {"judge_workers": 4}}),
+            &[("OJ_SERVER__JUDGE_WORKERS", "2"), ("OJ_SERVER__WALL_TIME_RATIO", "1.5"), ("OJ_SERVER__NAME", "judge")]);
+        assert_eq!(config["server"], json!({"judge_workers": 2, "wall_time_ratio": 1.5, "name": "judge"}));
+    }
+
+    fn problem(id: usize) -> Value
+    {
+        json!({"id": id, "name": "p", "type": "standard", "misc": {}, "cases": [
+            {"score": 100.0, "input_file": "missing.in", "answer_file": "missing.ans", "time_limit": 1000, "memory_limit": 0}
+        ]})
+    }
+
+    //a directory of config files for one test
+    fn write_files(test: &str, files: &[(&str, Value)]) -> std::path::PathBuf
+    {
+        let dir = std::env::temp_dir().join(format!("oj-{}-{}", test, std::process::id()));
+        for (name, value) in files
+        {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, value.to_string()).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn includes_are_expanded_and_keep_their_source()
+    {
+        let dir = write_files("includes", &[
+            ("config.json", json!({
+                "server": {"bind_address": "127.0.0.1", "bind_port": 12345},
+                "problems": [problem(0), {"include": "inc/list.json"}, {"include": "table.json"}],
+                "languages": []
+            })),
+            ("inc/list.json", json!([problem(1), {"include": "single.json"}])),
+            ("inc/single.json", problem(2)),
+            ("table.json", json!({"problems": [problem(3)]})),
+        ]);
+        let config = read_config(dir.join("config.json").to_str().unwrap()).unwrap();
+        let file = |name: &str| Some(dir.join(name).to_string_lossy().to_string());
+        assert_eq!(config.problems.iter().map(|x| x.id).collect::<Vec<usize>>(), vec![0, 1, 2, 3]);
+        assert_eq!(config.problem_sources, vec![
+            ProblemSource { file: None, path: "problems[0]".to_string() },
+            ProblemSource { file: file("inc/list.json"), path: "[0]".to_string() },
+            ProblemSource { file: file("inc/single.json"), path: String::new() },
+            ProblemSource { file: file("table.json"), path: "problems[0]".to_string() },
+        ]);
+
+        let locations: Vec<String> = validate::check(&config).iter()
+            .filter(|x| x.path.ends_with("input_file"))
+            .map(|x| x.location())
+            .collect();
+        assert_eq!(locations, vec![
+            "problems[0].cases[0].input_file".to_string(),
+            file("inc/list.json").unwrap() + ": [0].cases[0].input_file",
+            file("inc/single.json").unwrap() + ": cases[0].input_file",
+            file("table.json").unwrap() + ": problems[0].cases[0].input_file",
+        ]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn includes_in_a_loop_are_refused()
+    {
+        let dir = write_files("include-loop", &[
+            ("config.json", json!({"server": {}, "problems": [{"include": "loop.json"}], "languages": []})),
+            ("loop.json", json!([{"include": "loop.json"}])),
+        ]);
+        let err = read_config(dir.join("config.json").to_str().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("includes nested too deep"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_list_items_and_other_variables_are_ignored()
+    {
+        let config = overridden(json!({"problems": []}), &[("OJ_PROBLEMS__0__NAME", "x"), ("HOME", "/root")]);
+        assert_eq!(config, json!({"problems": []}));
+    }
+}
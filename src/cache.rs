@@ -0,0 +1,73 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::api::job::Job;
+use crate::api::state::JobState;
+use crate::storage::{self, storage};
+
+//queued and running jobs, the ones that still change
+//the storage has every job, this only saves reading the busy ones again
+//writes go to the storage first, so any entry may be dropped at any time
+pub struct JobCache
+{
+    jobs: HashMap<usize, Job>,
+    //ids in the order they came in, the oldest is dropped first
+    order: VecDeque<usize>,
+    capacity: usize,
+}
+
+impl JobCache
+{
+    pub fn new(capacity: usize) -> JobCache
+    {
+        JobCache {
+            jobs: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    //the job with this id, read from the storage if it is not cached
+    pub fn get(&mut self, id: usize) -> storage::Result<Option<Job>>
+    {
+        if let Some(job) = self.jobs.get(&id)
+        {
+            return Ok(Some(job.clone()));
+        }
+        let job = storage().get_job(id)?;
+        if let Some(job) = &job
+        {
+            self.keep(job);
+        }
+        Ok(job)
+    }
+
+    //remember the latest version of a job that was just stored, or forget it once it is done
+    pub fn keep(&mut self, job: &Job)
+    {
+        if job.state != JobState::Queueing && job.state != JobState::Running
+        {
+            self.remove(job.id);
+            return;
+        }
+        if self.jobs.insert(job.id, job.clone()).is_none()
+        {
+            self.order.push_back(job.id);
+        }
+        while self.jobs.len() > self.capacity
+        {
+            match self.order.pop_front()
+            {
+                Some(id) => self.jobs.remove(&id),
+                None => break,
+            };
+        }
+    }
+
+    pub fn remove(&mut self, id: usize)
+    {
+        if self.jobs.remove(&id).is_some()
+        {
+            self.order.retain(|x| *x != id);
+        }
+    }
+}
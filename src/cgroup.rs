@@ -0,0 +1,169 @@
+use std::fs::{create_dir, read_to_string, remove_dir, write};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use lazy_static::lazy_static;
+
+//controllers a judge leaf needs
+const CONTROLLERS: [&str; 2] = ["memory", "cpu"];
+//child of the server's own cgroup holding all judge leaves
+const JUDGE_GROUP: &str = "oj-judge";
+
+lazy_static!
+{
+    //None if cgroup v2 with the needed controllers can not be used
+    static ref JUDGE_ROOT: Option<PathBuf> = judge_root();
+}
+static LEAF_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+//find the cgroup v2 dir of this process and prepare JUDGE_GROUP under it,
+//the dir must be delegated to the server
+//cgroup v2 only enables controllers for children of a cgroup without processes,
+//so when the server is in the dir itself (the usual case in a container or a
+//systemd unit) it moves its own process into a new oj-server child first;
+//nothing is moved if the controllers can be enabled as is
+fn judge_root() -> Option<PathBuf>
+{
+    let mount = read_to_string("/proc/self/mountinfo").ok()?
+        .lines()
+        .find(|line| line.split(" - ").nth(1).is_some_and(|x| x.starts_with("cgroup2 ")))
+        .and_then(|line| line.split(' ').nth(4).map(PathBuf::from));
+    let own = read_to_string("/proc/self/cgroup").ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("0::").map(|x| x.trim_start_matches('/').to_string()));
+    let base = match (mount, own)
+    {
+        (Some(mount), Some(own)) => mount.join(own),
+        _ =>
+        {
+            log::warn!("cgroup v2 not found, using rlimit based accounting");
+            return None;
+        }
+    };
+
+    let controllers = read_to_string(base.join("cgroup.controllers")).unwrap_or_default();
+    if !CONTROLLERS.iter().all(|x| controllers.split_whitespace().any(|y| y == *x))
+    {
+        log::warn!("cgroup v2 controllers {:?} not available in {}, using rlimit based accounting", CONTROLLERS, base.display());
+        return None;
+    }
+
+    let enable = CONTROLLERS.iter().map(|x| "+".to_string() + x).collect::<Vec<String>>().join(" ");
+    let root = base.join(JUDGE_GROUP);
+    let prepared = make_dir(&root)
+        && (write(base.join("cgroup.subtree_control"), &enable).is_ok() || leave_for_child(&base, &enable))
+        && write(root.join("cgroup.subtree_control"), &enable).is_ok();
+    if !prepared
+    {
+        log::warn!("Failed to prepare cgroup {}, using rlimit based accounting", root.display());
+        return None;
+    }
+    Some(root)
+}
+
+//move the server into base/oj-server so the controllers can be enabled in base
+fn leave_for_child(base: &Path, enable: &str) -> bool
+{
+    let server = base.join("oj-server");
+    let moved = make_dir(&server) && write(server.join("cgroup.procs"), std::process::id().to_string()).is_ok();
+    if moved
+    {
+        log::info!("Moved the server into cgroup {} to enable {:?} for judging", server.display(), CONTROLLERS);
+    }
+    moved && write(base.join("cgroup.subtree_control"), enable).is_ok()
+}
+
+fn make_dir(path: &Path) -> bool
+{
+    match create_dir(path)
+    {
+        Ok(()) => true,
+        Err(err) => err.kind() == ErrorKind::AlreadyExists,
+    }
+}
+
+//a cgroup v2 leaf for one run, removed on drop
+pub struct Cgroup
+{
+    path: PathBuf,
+}
+
+impl Cgroup
+{
+    //create a leaf with memory.max set, 0 means unlimited
+    pub fn create(memory_limit: u64) -> Option<Cgroup>
+    {
+        let root = JUDGE_ROOT.as_ref()?;
+        let path = root.join(format!("{}-{}", std::process::id(), LEAF_COUNT.fetch_add(1, Ordering::Relaxed)));
+        if create_dir(&path).is_err()
+        {
+            return None;
+        }
+        let cgroup = Cgroup { path };
+        if memory_limit != 0
+        {
+            if write(cgroup.path.join("memory.max"), memory_limit.to_string()).is_err()
+            {
+                return None;
+            }
+            //swapping would hide memory usage
+            let _ = write(cgroup.path.join("memory.swap.max"), "0");
+        }
+        Some(cgroup)
+    }
+
+    //writing "0" into it moves the writing process into the leaf
+    pub fn procs(&self) -> PathBuf
+    {
+        self.path.join("cgroup.procs")
+    }
+
+    //bytes, memory.peak needs linux 5.19
+    pub fn memory_peak(&self) -> Option<u64>
+    {
+        read_to_string(self.path.join("memory.peak")).ok()?.trim().parse().ok()
+    }
+
+    //microseconds
+    pub fn cpu_usage(&self) -> Option<u64>
+    {
+        self.read_key("cpu.stat", "usage_usec")
+    }
+
+    pub fn oom_killed(&self) -> bool
+    {
+        self.read_key("memory.events", "oom_kill").is_some_and(|x| x > 0)
+    }
+
+    //cgroup.kill needs linux 5.14
+    pub fn kill(&self)
+    {
+        let _ = write(self.path.join("cgroup.kill"), "1");
+    }
+
+    fn read_key(&self, file: &str, key: &str) -> Option<u64>
+    {
+        read_to_string(self.path.join(file)).ok()?
+            .lines()
+            .find_map(|line| line.strip_prefix(key).and_then(|x| x.trim().parse().ok()))
+    }
+}
+
+impl Drop for Cgroup
+{
+    fn drop(&mut self)
+    {
+        //a leaf can only be removed after its processes are gone
+        self.kill();
+        for _ in 0..100
+        {
+            if remove_dir(&self.path).is_ok()
+            {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        log::warn!("Failed to remove cgroup {}", self.path.display());
+    }
+}
@@ -1,17 +1,31 @@
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use tokio::sync::{broadcast, Mutex};
 use lazy_static::lazy_static;
-use rusqlite::Connection;
 
+use crate::arg::Problem;
 use crate::api::job::Job;
 use crate::api::user::User;
 use crate::api::contest::Contest;
+use crate::api::scoreboard::{RanklistSender, ScoreboardKey};
+use crate::cache::JobCache;
+use crate::queue::JobQueue;
+use crate::storage::Storage;
 
 //some globals
 lazy_static!
 {
-    pub static ref JOB_LIST: Arc<Mutex<Vec<Job>>> = Arc::new(Mutex::new(Vec::new()));
     pub static ref USER_LIST: Arc<Mutex<Vec<User>>> = Arc::new(Mutex::new(Vec::new()));
+    pub static ref PROBLEM_LIST: Arc<Mutex<Vec<Problem>>> = Arc::new(Mutex::new(Vec::new()));
     pub static ref CONTEST_LIST: Arc<Mutex<Vec<Contest>>> = Arc::new(Mutex::new(Vec::new()));
-    pub static ref DATABASE: Arc<Mutex<Connection>> = Arc::new(Mutex::new(Connection::open("database.db").expect("Failed to open database")));
-}
\ No newline at end of file
+    pub static ref JOB_QUEUE: JobQueue = JobQueue::new();
+    //active jobs, lock it around every read-modify-write of a job
+    pub static ref JOB_CACHE: Mutex<JobCache> = Mutex::new(JobCache::new(1024));
+    //every job snapshot, for the event streams
+    pub static ref JOB_EVENTS: broadcast::Sender<Job> = broadcast::channel(256).0;
+    //rebuilt ranklists for the scoreboard clients
+    pub static ref SCOREBOARDS: Mutex<HashMap<ScoreboardKey, RanklistSender>> = Mutex::new(HashMap::new());
+}
+
+//opened once at startup from server.storage, see storage::storage
+pub static STORAGE: OnceLock<Box<dyn Storage>> = OnceLock::new();
\ No newline at end of file
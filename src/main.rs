@@ -1,15 +1,17 @@
-use actix_web::{middleware::Logger, post, web, App, HttpServer, Responder};
-use api::job::{job_consumer, job_producer, Job};
-use env_logger;
-use log;
-use sql::{create_tables, drop_all_tables, read_contests, read_jobs, read_users};
+use actix_web::{middleware::{from_fn, Logger}, post, web, App, HttpServer, Responder};
+use api::job::{job_consumer, job_worker, queue_waiting_jobs, recover_interrupted_jobs, Job};
 use tokio::{task, sync::mpsc};
 use actix_cors::Cors;
 
 mod arg;
 mod globals;
 mod api;
-mod sql;
+mod storage;
+mod runner;
+mod cgroup;
+mod queue;
+mod cache;
+mod validate;
 
 // DO NOT REMOVE: used in automatic testing
 #[post("/internal/exit")]
@@ -18,7 +20,7 @@ async fn exit() -> impl Responder
 {
     log::info!("Shutdown as requested");
     std::process::exit(0);
-    format!("Exited")
+    "Exited"
 }
 
 #[actix_web::main]
@@ -27,48 +29,90 @@ async fn main() -> std::io::Result<()>
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
 
     //read command line
-    let (config, flush) = arg::get_arg()?;
+    let args = arg::get_arg()?;
+    let config = args.config;
+    let shared_config = web::Data::new(arg::SharedConfig::new(args.config_file, config.clone()));
     let address = config.server.bind_address.clone();
     let port = config.server.bind_port;
 
-    //sql
-    if flush
+    //storage
+    let opened = storage::open(&config.server)
+        .map_err(|err| std::io::Error::other(format!("Failed to open storage: {}", err)))?;
+    let _ = globals::STORAGE.set(opened);
+    if args.flush
     {
-        let _ = drop_all_tables().await;
+        let _ = storage::storage().clear();
     }
 
-    let _ = create_tables().await;
-    let _ = read_jobs().await;
-    let _ = read_contests().await;
-    let _ = read_users().await;
+    //refuse to run on a database we can not read
+    storage::storage().prepare()
+        .map_err(|err| std::io::Error::other(format!("Failed to migrate database: {}", err)))?;
+    if args.migrate_only
+    {
+        log::info!("Database is up to date");
+        return Ok(());
+    }
+    //serving empty lists would hide the stored data and let new rows clash with it
+    storage::load_all().await
+        .map_err(|err| std::io::Error::other(format!("Failed to load data: {}", err)))?;
+    api::problem::import_config_problems(&config, None).await;
+    recover_interrupted_jobs(args.fail_interrupted).await;
+    queue_waiting_jobs().await;
+    api::auth::init_root_password(&config).await;
 
     //用于非阻塞评测和更新的异步线程
     let (tx, rx) = mpsc::channel::<Job>(32);
-    task::spawn(job_producer(tx, config.clone()));
+    for _ in 0..config.server.judge_workers.max(1)
+    {
+        task::spawn(job_worker(tx.clone(), shared_config.clone()));
+    }
     task::spawn(job_consumer(rx));
+    task::spawn(api::reload::reload_on_hangup(shared_config.clone()));
 
     HttpServer::new(move || {
         App::new()
             .wrap(Logger::default())
+            //inside Cors, so that its 401 and 403 responses get the cors headers too
+            .wrap(from_fn(api::auth::authorize))
             //allow web requests
             .wrap(
                 Cors::default()
                     .allow_any_origin()
                     .allow_any_method()
                     .allow_any_header()
+                    //the job count of paged GET /jobs
+                    .expose_headers(["X-Total-Count"])
             )
-            .app_data(web::Data::new(config.clone()))
+            .app_data(shared_config.clone())
+            //test data archives come as raw bodies
+            .app_data(web::PayloadConfig::new(api::problem::UPLOAD_LIMIT))
             .service(api::job::post_jobs)
+            //before /jobs/{jobid}, which would take "events" and "rejudge" as job ids
+            .service(api::events::get_jobs_events)
+            .service(api::events::get_jobs_id_events)
+            .service(api::rejudge::post_jobs_rejudge)
+            .service(api::rejudge::get_jobs_rejudge_id)
             .service(api::job::get_jobs_id)
             .service(api::job::get_jobs_query)
             .service(api::job::put_jobs_id)
             .service(api::job::delete_jobs)
+            .service(api::job::cancel_jobs_id)
+            .service(api::job::get_queue)
+            .service(api::auth::post_login)
             .service(api::user::post_users)
             .service(api::user::get_users)
+            .service(api::problem::post_problems)
+            .service(api::problem::get_problems)
+            .service(api::problem::get_problems_id)
+            .service(api::problem::put_problems_id)
+            .service(api::problem::put_problems_data)
+            .service(api::problem::delete_problems_id)
             .service(api::contest::post_contests)
             .service(api::contest::get_contests)
             .service(api::contest::get_contests_id)
             .service(api::contest::get_contests_ranklist)
+            .service(api::scoreboard::get_contests_ranklist_ws)
+            .service(api::reload::post_reload)
             // DO NOT REMOVE: used in automatic testing
             .service(exit)
     })
@@ -0,0 +1,89 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use tokio::sync::{Mutex, Notify};
+
+//judging order of queued jobs, higher first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority
+{
+    //rejudges, so they do not hold back new submissions
+    Low,
+    Normal,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Entry
+{
+    priority: Priority,
+    //enqueue order, first in first out within a priority
+    seq: u64,
+    id: usize,
+}
+
+impl Ord for Entry
+{
+    fn cmp(&self, other: &Self) -> Ordering
+    {
+        self.priority.cmp(&other.priority).then(other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for Entry
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering>
+    {
+        Some(self.cmp(other))
+    }
+}
+
+//ids of jobs waiting for a judge worker
+pub struct JobQueue
+{
+    entries: Mutex<(BinaryHeap<Entry>, u64)>,
+    notify: Notify,
+}
+
+impl JobQueue
+{
+    pub fn new() -> JobQueue
+    {
+        JobQueue {
+            entries: Mutex::new((BinaryHeap::new(), 0)),
+            notify: Notify::new(),
+        }
+    }
+
+    pub async fn push(&self, id: usize, priority: Priority)
+    {
+        let mut lock = self.entries.lock().await;
+        let seq = lock.1;
+        lock.1 += 1;
+        lock.0.push(Entry { priority, seq, id });
+        drop(lock);
+        self.notify.notify_one();
+    }
+
+    //wait until a job is queued and take it, each id is handed to one worker only
+    pub async fn pop(&self) -> usize
+    {
+        loop
+        {
+            if let Some(entry) = self.entries.lock().await.0.pop()
+            {
+                return entry.id;
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    //drop a job that should no longer be judged
+    pub async fn remove(&self, id: usize)
+    {
+        self.entries.lock().await.0.retain(|x| x.id != id);
+    }
+
+    pub async fn len(&self) -> usize
+    {
+        self.entries.lock().await.0.len()
+    }
+}
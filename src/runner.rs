@@ -0,0 +1,813 @@
+use std::ffi::{CStr, CString};
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::{Duration, Instant};
+use libc::c_char;
+use tokio::io::{sink, copy, AsyncReadExt};
+use tokio::process::Command;
+use tokio::{task, time};
+
+use crate::cgroup::Cgroup;
+
+//the cpu time of a running program is checked every this many milliseconds
+const CPU_CHECK_ROUNDS: u64 = 10;
+//largest output file a program may write, in bytes
+const OUTPUT_LIMIT: u64 = 64 * 1024 * 1024;
+//processes (including threads) a program may create besides itself
+const PROCESS_LIMIT: u64 = 1;
+//uid and gid the program runs as inside its user namespace
+const SANDBOX_ID: u32 = 65534;
+//mount options of the private working dir
+const SANDBOX_TMPFS: &str = "size=16m,mode=0777";
+//host dirs bound read-only into the sandbox, if they exist, for the dynamic loader and libraries
+const SANDBOX_PATHS: [&str; 4] = ["/bin", "/lib", "/lib64", "/usr"];
+//where the program is bound in the sandbox
+const SANDBOX_PROGRAM: &str = "/main";
+//largest part of the compiler messages that is kept, in bytes
+const COMPILE_INFO_LIMIT: u64 = 4096;
+//fds marked close-on-exec one by one when close_range is missing
+const FD_SCAN_LIMIT: libc::c_int = 1024;
+
+//records the forked processes send back through the report pipe
+const REPORT_ERROR: i64 = 0;
+const REPORT_STATUS: i64 = 1;
+type Report = [i64; 4];
+
+//setup steps, reported with errors from the child side
+const STAGES: [&str; 11] = [
+    "setrlimit", "dup2", "seccomp", "execve", "unshare",
+    "id map", "mount", "fork", "wait", "chdir", "cgroup",
+];
+const STAGE_SETRLIMIT: i64 = 0;
+const STAGE_DUP2: i64 = 1;
+const STAGE_SECCOMP: i64 = 2;
+const STAGE_EXECVE: i64 = 3;
+const STAGE_UNSHARE: i64 = 4;
+const STAGE_ID_MAP: i64 = 5;
+const STAGE_MOUNT: i64 = 6;
+const STAGE_FORK: i64 = 7;
+const STAGE_WAIT: i64 = 8;
+const STAGE_CHDIR: i64 = 9;
+const STAGE_CGROUP: i64 = 10;
+
+//how a judged program ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome
+{
+    Exited,
+    RuntimeError,
+    TimeLimitExceeded,
+    MemoryLimitExceeded,
+    SystemError,
+}
+
+//what the runner reports for one run
+#[derive(Debug, Clone)]
+pub struct RunReport
+{
+    pub outcome: Outcome,
+    //wall clock, microseconds
+    pub time: u64,
+    //user + sys, microseconds
+    pub cpu_time: u64,
+    //bytes
+    pub memory: u64,
+}
+
+impl RunReport
+{
+    fn system_error() -> RunReport
+    {
+        RunReport {
+            outcome: Outcome::SystemError,
+            time: 0,
+            cpu_time: 0,
+            memory: 0,
+        }
+    }
+}
+
+//how a compilation ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompileOutcome
+{
+    Success,
+    Error,
+    Timeout,
+}
+
+//what the runner reports for one compilation
+#[derive(Debug, Clone)]
+pub struct CompileReport
+{
+    pub outcome: CompileOutcome,
+    //truncated compiler stderr
+    pub info: String,
+}
+
+//resource limits of one run, 0 means unlimited
+#[derive(Debug, Clone, Copy)]
+pub struct Limits
+{
+    //cpu time, microseconds
+    pub time_limit: u64,
+    //wall clock, microseconds
+    pub wall_time_limit: u64,
+    //bytes
+    pub memory_limit: u64,
+}
+
+//run `program` with stdin from `in_file` and stdout to `out_file`
+//with `sandbox` set, the program is isolated in its own namespaces with a seccomp allowlist
+//its root is a private tmpfs mounted at `sandbox`, which holds the program and read-only binds of SANDBOX_PATHS
+pub async fn run(program: &str, in_file: &str, out_file: &str, limits: Limits, sandbox: Option<&str>) -> RunReport
+{
+    let program = program.to_string();
+    let in_file = in_file.to_string();
+    let out_file = out_file.to_string();
+    let sandbox = sandbox.map(|x| x.to_string());
+    match task::spawn_blocking(move || run_blocking(&program, &in_file, &out_file, limits, sandbox.as_deref())).await
+    {
+        Ok(Ok(report)) => report,
+        Ok(Err(err)) =>
+        {
+            log::error!("Failed to run {}", err);
+            RunReport::system_error()
+        }
+        Err(_) => RunReport::system_error(),
+    }
+}
+
+//run a compiler with a wall clock `time_limit` in microseconds and a `memory_limit` in bytes, 0 means unlimited
+//the compiler gets its own process group (and cgroup if available) so whatever it
+//spawns is killed with it on timeout
+pub async fn compile(command: &str, args: &[String], time_limit: u64, memory_limit: u64) -> Result<CompileReport>
+{
+    let cgroup = Cgroup::create(memory_limit);
+    let procs = match &cgroup
+    {
+        Some(cgroup) => Some(CString::new(cgroup.procs().into_os_string().into_encoded_bytes())
+            .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?),
+        None => None,
+    };
+
+    let mut command = Command::new(command);
+    command.args(args)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true);
+    //runs between fork and exec, only async-signal-safe calls here
+    unsafe {
+        command.pre_exec(move || {
+            if libc::setpgid(0, 0) != 0
+            {
+                return Err(Error::last_os_error());
+            }
+            match &procs
+            {
+                Some(procs) if !write_file(procs, b"0") => return Err(Error::last_os_error()),
+                Some(_) => {}
+                None if memory_limit != 0 =>
+                {
+                    let limit = libc::rlimit { rlim_cur: memory_limit, rlim_max: memory_limit };
+                    if libc::setrlimit(libc::RLIMIT_AS, &limit) != 0
+                    {
+                        return Err(Error::last_os_error());
+                    }
+                }
+                None => {}
+            }
+            Ok(())
+        });
+    }
+    let mut child = command.spawn()?;
+    let pid = child.id();
+    let mut stderr = child.stderr.take().ok_or_else(|| Error::other("No compiler stderr"))?;
+
+    let finish = async {
+        let mut info = Vec::new();
+        (&mut stderr).take(COMPILE_INFO_LIMIT).read_to_end(&mut info).await?;
+        //keep draining so a chatty compiler does not block on a full pipe
+        let truncated = copy(&mut stderr, &mut sink()).await? > 0;
+        let status = child.wait().await?;
+        Ok::<_, Error>((info, truncated, status))
+    };
+    let limit = if time_limit == 0 { Duration::MAX } else { Duration::from_micros(time_limit) };
+    let report = match time::timeout(limit, finish).await
+    {
+        Ok(result) =>
+        {
+            let (info, truncated, status) = result?;
+            let mut info = String::from_utf8_lossy(&info).to_string();
+            if truncated
+            {
+                info += "\n... (truncated)";
+            }
+            if cgroup.as_ref().is_some_and(|x| x.oom_killed())
+            {
+                info += "\nCompiler exceeded the memory limit";
+            }
+            CompileReport {
+                outcome: if status.success() { CompileOutcome::Success } else { CompileOutcome::Error },
+                info,
+            }
+        }
+        Err(_) =>
+        {
+            if let Some(pid) = pid
+            {
+                unsafe {
+                    libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+                }
+            }
+            let _ = child.kill().await;
+            CompileReport {
+                outcome: CompileOutcome::Timeout,
+                info: format!("Compilation exceeded {} ms", time_limit / 1000),
+            }
+        }
+    };
+    Ok(report)
+}
+
+//everything the forked processes need, prepared before fork since they
+//may only make async-signal-safe calls
+struct Setup
+{
+    program: CString,
+    argv: [*const c_char; 2],
+    envp: [*const c_char; 1],
+    input: File,
+    output: File,
+    null: File,
+    rlimits: Vec<(libc::__rlimit_resource_t, u64)>,
+    cgroup_procs: Option<CString>,
+    report: RawFd,
+    isolation: Option<Isolation>,
+}
+
+struct Isolation
+{
+    work_dir: CString,
+    tmpfs_options: CString,
+    //host path and mount point under work_dir
+    binds: Vec<(CString, CString)>,
+    //SANDBOX_PROGRAM, under work_dir and as seen from inside
+    program_target: CString,
+    program: CString,
+    proc_dir: CString,
+    uid_map: String,
+    gid_map: String,
+    filter: Vec<libc::sock_filter>,
+}
+
+fn run_blocking(program: &str, in_file: &str, out_file: &str, limits: Limits, sandbox: Option<&str>) -> Result<RunReport>
+{
+    let invalid = |_| Error::new(ErrorKind::InvalidInput, "Invalid path");
+    let program = CString::new(std::fs::canonicalize(program)?.into_os_string().into_encoded_bytes()).map_err(invalid)?;
+
+    let mut rlimits: Vec<(libc::__rlimit_resource_t, u64)> = vec![
+        (libc::RLIMIT_FSIZE, OUTPUT_LIMIT),
+        (libc::RLIMIT_NPROC, PROCESS_LIMIT),
+        (libc::RLIMIT_CORE, 0),
+    ];
+    if limits.time_limit != 0
+    {
+        //backstop in whole seconds, the cpu time is checked precisely below
+        rlimits.push((libc::RLIMIT_CPU, limits.time_limit / 1_000_000 + 1));
+    }
+    //with a cgroup, memory.max limits the memory instead of the address space
+    let cgroup = Cgroup::create(limits.memory_limit);
+    if limits.memory_limit != 0 && cgroup.is_none()
+    {
+        rlimits.push((libc::RLIMIT_AS, limits.memory_limit));
+    }
+    let cgroup_procs = match &cgroup
+    {
+        Some(cgroup) => Some(CString::new(cgroup.procs().into_os_string().into_encoded_bytes()).map_err(invalid)?),
+        None => None,
+    };
+
+    let isolation = match sandbox
+    {
+        Some(work_dir) =>
+        {
+            create_dir_all(work_dir)?;
+            let mut binds = Vec::new();
+            for path in SANDBOX_PATHS.iter().filter(|x| std::path::Path::new(x).exists())
+            {
+                binds.push((CString::new(*path).map_err(invalid)?, CString::new(work_dir.to_string() + path).map_err(invalid)?));
+            }
+            Some(Isolation {
+                work_dir: CString::new(work_dir).map_err(invalid)?,
+                tmpfs_options: CString::new(SANDBOX_TMPFS).map_err(invalid)?,
+                binds,
+                program_target: CString::new(work_dir.to_string() + SANDBOX_PROGRAM).map_err(invalid)?,
+                program: CString::new(SANDBOX_PROGRAM).map_err(invalid)?,
+                proc_dir: CString::new(work_dir.to_string() + "/proc").map_err(invalid)?,
+                uid_map: format!("{} {} 1", SANDBOX_ID, unsafe { libc::getuid() }),
+                gid_map: format!("{} {} 1", SANDBOX_ID, unsafe { libc::getgid() }),
+                filter: seccomp_filter(),
+            })
+        }
+        None => None,
+    };
+
+    let mut pipe: [libc::c_int; 2] = [0; 2];
+    if unsafe { libc::pipe2(pipe.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) } != 0
+    {
+        return Err(Error::last_os_error());
+    }
+    let setup = Setup {
+        argv: [program.as_ptr(), std::ptr::null()],
+        envp: [std::ptr::null()],
+        program,
+        input: File::open(in_file)?,
+        output: OpenOptions::new().write(true).create(true).truncate(true).open(out_file)?,
+        null: OpenOptions::new().write(true).open("/dev/null")?,
+        rlimits,
+        cgroup_procs,
+        report: pipe[1],
+        isolation,
+    };
+
+    let start = Instant::now();
+    let pid = unsafe { libc::fork() };
+    if pid < 0
+    {
+        let err = Error::last_os_error();
+        unsafe {
+            libc::close(pipe[0]);
+            libc::close(pipe[1]);
+        }
+        return Err(err);
+    }
+    if pid == 0
+    {
+        if let Some(procs) = &setup.cgroup_procs
+        {
+            if !write_file(procs, b"0")
+            {
+                fail(&setup, STAGE_CGROUP);
+            }
+        }
+        match setup.isolation
+        {
+            Some(ref isolation) => isolate(&setup, isolation),
+            None => exec_program(&setup),
+        }
+    }
+    unsafe { libc::close(pipe[1]); }
+    drop(setup);
+
+    //wait for the child, kill it once its cpu time or the wall clock runs out
+    let wall_limit = Duration::from_micros(limits.wall_time_limit);
+    let mut status: libc::c_int = 0;
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    let mut killed = false;
+    let mut round: u64 = 0;
+    loop
+    {
+        let ret = unsafe { libc::wait4(pid, &mut status, libc::WNOHANG, &mut usage) };
+        if ret == pid
+        {
+            break;
+        }
+        if ret < 0
+        {
+            let err = Error::last_os_error();
+            if err.kind() == ErrorKind::Interrupted
+            {
+                continue;
+            }
+            unsafe { libc::close(pipe[0]); }
+            return Err(err);
+        }
+        round += 1;
+        let out_of_time = (limits.wall_time_limit != 0 && start.elapsed() > wall_limit)
+            || (limits.time_limit != 0 && round.is_multiple_of(CPU_CHECK_ROUNDS) && running_cpu_time(pid, cgroup.as_ref()) > limits.time_limit);
+        if out_of_time && !killed
+        {
+            unsafe { libc::kill(pid, libc::SIGKILL); }
+            if let Some(cgroup) = &cgroup
+            {
+                cgroup.kill();
+            }
+            killed = true;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let time = start.elapsed().as_micros() as u64;
+    //ru_maxrss is in kilobytes
+    let mut memory = usage.ru_maxrss as u64 * 1024;
+    let mut cpu_time = timeval_micros(usage.ru_utime) + timeval_micros(usage.ru_stime);
+
+    //the child side reports setup errors, and in isolation the status of the program
+    let mut report: Report = [0; 4];
+    let read = unsafe { libc::read(pipe[0], report.as_mut_ptr() as *mut libc::c_void, std::mem::size_of::<Report>()) };
+    unsafe { libc::close(pipe[0]); }
+    if read == std::mem::size_of::<Report>() as isize
+    {
+        if report[0] == REPORT_ERROR
+        {
+            let stage = STAGES.get(report[2] as usize).unwrap_or(&"setup");
+            return Err(Error::other(format!("{}: {}", stage, Error::from_raw_os_error(report[1] as i32))));
+        }
+        if report[0] == REPORT_STATUS && !killed
+        {
+            status = report[1] as libc::c_int;
+            memory = report[2] as u64 * 1024;
+            cpu_time = report[3] as u64;
+        }
+    }
+    else if setup_failed(killed, status, sandbox.is_some())
+    {
+        return Err(Error::other("Sandbox exited without a report"));
+    }
+
+    let accounted = cgroup.is_some();
+    //the cgroup numbers cover every process of the run and exclude the pages copied by fork
+    let mut oom_killed = false;
+    if let Some(cgroup) = &cgroup
+    {
+        memory = cgroup.memory_peak().unwrap_or(memory);
+        cpu_time = cgroup.cpu_usage().unwrap_or(cpu_time);
+        oom_killed = cgroup.oom_killed();
+    }
+    drop(cgroup);
+
+    let outcome = if killed
+    {
+        Outcome::TimeLimitExceeded
+    }
+    else if oom_killed
+    {
+        Outcome::MemoryLimitExceeded
+    }
+    else if limits.time_limit != 0 && cpu_time > limits.time_limit
+    {
+        Outcome::TimeLimitExceeded
+    }
+    else if libc::WIFEXITED(status)
+    {
+        match libc::WEXITSTATUS(status)
+        {
+            0 => Outcome::Exited,
+            _ => Outcome::RuntimeError,
+        }
+    }
+    else if libc::WIFSIGNALED(status)
+    {
+        match libc::WTERMSIG(status)
+        {
+            libc::SIGXCPU => Outcome::TimeLimitExceeded,
+            //under RLIMIT_AS a failed allocation aborts or dereferences null
+            libc::SIGABRT | libc::SIGSEGV if limits.memory_limit != 0 && !accounted => Outcome::MemoryLimitExceeded,
+            _ => Outcome::RuntimeError,
+        }
+    }
+    else
+    {
+        Outcome::RuntimeError
+    };
+    let outcome = match outcome
+    {
+        Outcome::Exited | Outcome::RuntimeError if limits.memory_limit != 0 && memory > limits.memory_limit => Outcome::MemoryLimitExceeded,
+        _ => outcome,
+    };
+
+    Ok(RunReport { outcome, time, cpu_time, memory })
+}
+
+//in isolation the program status always comes through the pipe
+fn setup_failed(killed: bool, status: libc::c_int, isolated: bool) -> bool
+{
+    isolated && !killed && !(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0)
+}
+
+//cpu time used so far, from the cgroup or else /proc of `pid` and its descendants
+//in isolation the program is a grandchild, under the init of its pid namespace
+fn running_cpu_time(pid: libc::pid_t, cgroup: Option<&Cgroup>) -> u64
+{
+    if let Some(usage) = cgroup.and_then(|x| x.cpu_usage())
+    {
+        return usage;
+    }
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as u64;
+    let mut total = 0;
+    let mut pids = vec![pid.to_string()];
+    while let Some(pid) = pids.pop()
+    {
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap_or_default();
+        //utime and stime are the 14th and 15th fields, counted after the parenthesized command
+        total += stat.rsplit_once(')')
+            .map(|(_, x)| x.split_whitespace().skip(11).take(2).filter_map(|y| y.parse::<u64>().ok()).sum())
+            .unwrap_or(0);
+        let children = std::fs::read_to_string(format!("/proc/{0}/task/{0}/children", pid)).unwrap_or_default();
+        pids.extend(children.split_whitespace().map(|x| x.to_string()));
+    }
+    total * 1_000_000 / ticks
+}
+
+fn timeval_micros(time: libc::timeval) -> u64
+{
+    time.tv_sec as u64 * 1_000_000 + time.tv_usec as u64
+}
+
+fn send_report(fd: RawFd, report: Report)
+{
+    unsafe {
+        libc::write(fd, report.as_ptr() as *const libc::c_void, std::mem::size_of::<Report>());
+    }
+}
+
+fn fail(setup: &Setup, stage: i64) -> !
+{
+    let errno = Error::last_os_error().raw_os_error().unwrap_or(0) as i64;
+    send_report(setup.report, [REPORT_ERROR, errno, stage, 0]);
+    unsafe { libc::_exit(127) }
+}
+
+//the last step in the forked process: apply limits and exec the program
+fn exec_program(setup: &Setup) -> !
+{
+    unsafe {
+        for (resource, value) in setup.rlimits.iter()
+        {
+            let rlimit = libc::rlimit { rlim_cur: *value, rlim_max: *value };
+            if libc::setrlimit(*resource, &rlimit) != 0
+            {
+                fail(setup, STAGE_SETRLIMIT);
+            }
+        }
+        if libc::dup2(setup.input.as_raw_fd(), 0) < 0
+            || libc::dup2(setup.output.as_raw_fd(), 1) < 0
+            || libc::dup2(setup.null.as_raw_fd(), 2) < 0
+        {
+            fail(setup, STAGE_DUP2);
+        }
+        //only stdin, stdout and stderr reach the program
+        if libc::syscall(libc::SYS_close_range, 3, libc::c_uint::MAX, libc::CLOSE_RANGE_CLOEXEC) != 0
+        {
+            for fd in 3..FD_SCAN_LIMIT
+            {
+                libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+            }
+        }
+        if let Some(isolation) = &setup.isolation
+        {
+            let program = libc::sock_fprog {
+                len: isolation.filter.len() as u16,
+                filter: isolation.filter.as_ptr() as *mut libc::sock_filter,
+            };
+            if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0
+                || libc::syscall(libc::SYS_seccomp, libc::SECCOMP_SET_MODE_FILTER, 0, &program as *const libc::sock_fprog) != 0
+            {
+                fail(setup, STAGE_SECCOMP);
+            }
+        }
+        let program = setup.isolation.as_ref().map_or(&setup.program, |x| &x.program);
+        libc::execve(program.as_ptr(), setup.argv.as_ptr(), setup.envp.as_ptr());
+        fail(setup, STAGE_EXECVE)
+    }
+}
+
+//enter new user, mount, pid and net namespaces, then run the program as pid 2
+//under a minimal init that reports its status
+fn isolate(setup: &Setup, isolation: &Isolation) -> !
+{
+    unsafe {
+        if libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNS | libc::CLONE_NEWPID | libc::CLONE_NEWNET) != 0
+        {
+            fail(setup, STAGE_UNSHARE);
+        }
+        if !write_file(c"/proc/self/setgroups", b"deny")
+            || !write_file(c"/proc/self/uid_map", isolation.uid_map.as_bytes())
+            || !write_file(c"/proc/self/gid_map", isolation.gid_map.as_bytes())
+        {
+            fail(setup, STAGE_ID_MAP);
+        }
+
+        //a private tmpfs as the new root, holding the program and read-only binds of the toolchain
+        //everything else of the host, like the test data and other jobs, stays out of sight
+        let root = c"/".as_ptr();
+        let tmpfs = c"tmpfs".as_ptr();
+        if libc::mount(std::ptr::null(), root, std::ptr::null(), libc::MS_REC | libc::MS_PRIVATE, std::ptr::null()) != 0
+            || !readonly_root()
+            || libc::mount(tmpfs, isolation.work_dir.as_ptr(), tmpfs, libc::MS_NOSUID | libc::MS_NODEV, isolation.tmpfs_options.as_ptr() as *const libc::c_void) != 0
+        {
+            fail(setup, STAGE_MOUNT);
+        }
+        //binds of a read-only mount are read-only too
+        for (source, target) in isolation.binds.iter()
+        {
+            if libc::mkdir(target.as_ptr(), 0o755) != 0
+                || libc::mount(source.as_ptr(), target.as_ptr(), std::ptr::null(), libc::MS_BIND | libc::MS_REC, std::ptr::null()) != 0
+            {
+                fail(setup, STAGE_MOUNT);
+            }
+        }
+        let program = libc::open(isolation.program_target.as_ptr(), libc::O_WRONLY | libc::O_CREAT | libc::O_CLOEXEC, 0o755);
+        if program < 0
+            || libc::close(program) != 0
+            || libc::mount(setup.program.as_ptr(), isolation.program_target.as_ptr(), std::ptr::null(), libc::MS_BIND, std::ptr::null()) != 0
+            || libc::mkdir(isolation.proc_dir.as_ptr(), 0o555) != 0
+        {
+            fail(setup, STAGE_MOUNT);
+        }
+
+        //closed only when this process is gone, so that init can tell if it missed its death signal
+        let mut alive: [libc::c_int; 2] = [0; 2];
+        if libc::pipe2(alive.as_mut_ptr(), libc::O_CLOEXEC) != 0
+        {
+            fail(setup, STAGE_FORK);
+        }
+
+        //the first child is pid 1 of the new namespace
+        let init = libc::fork();
+        if init < 0
+        {
+            fail(setup, STAGE_FORK);
+        }
+        if init == 0
+        {
+            //its parent is outside the namespace, getppid() is always 0
+            libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL, 0, 0, 0);
+            libc::close(alive[1]);
+            let mut parent = libc::pollfd { fd: alive[0], events: libc::POLLIN, revents: 0 };
+            if libc::poll(&mut parent, 1, 0) != 0
+            {
+                libc::_exit(0);
+            }
+
+            //proc can only be mounted while the host's is still visible, so before the switch to the new root
+            let proc = c"proc".as_ptr();
+            libc::mount(proc, isolation.proc_dir.as_ptr(), proc, libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC, std::ptr::null());
+            let dot = c".".as_ptr();
+            if libc::chdir(isolation.work_dir.as_ptr()) != 0
+            {
+                fail(setup, STAGE_CHDIR);
+            }
+            //stack the old root under the new one and detach it
+            if libc::syscall(libc::SYS_pivot_root, dot, dot) != 0
+                || libc::umount2(dot, libc::MNT_DETACH) != 0
+            {
+                fail(setup, STAGE_MOUNT);
+            }
+            if libc::chdir(root) != 0
+            {
+                fail(setup, STAGE_CHDIR);
+            }
+
+            let pid = libc::fork();
+            if pid < 0
+            {
+                fail(setup, STAGE_FORK);
+            }
+            if pid == 0
+            {
+                libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL, 0, 0, 0);
+                if libc::getppid() != 1
+                {
+                    libc::_exit(0);
+                }
+                exec_program(setup);
+            }
+            let mut status: libc::c_int = 0;
+            let mut usage: libc::rusage = std::mem::zeroed();
+            while libc::wait4(pid, &mut status, 0, &mut usage) < 0
+            {
+                if Error::last_os_error().kind() != ErrorKind::Interrupted
+                {
+                    fail(setup, STAGE_WAIT);
+                }
+            }
+            let cpu_time = timeval_micros(usage.ru_utime) + timeval_micros(usage.ru_stime);
+            send_report(setup.report, [REPORT_STATUS, status as i64, usage.ru_maxrss, cpu_time as i64]);
+            libc::_exit(0);
+        }
+        let mut status: libc::c_int = 0;
+        while libc::waitpid(init, &mut status, 0) < 0
+        {
+            if Error::last_os_error().kind() != ErrorKind::Interrupted
+            {
+                fail(setup, STAGE_WAIT);
+            }
+        }
+        libc::_exit(0)
+    }
+}
+
+fn write_file(path: &CStr, content: &[u8]) -> bool
+{
+    unsafe {
+        let fd = libc::open(path.as_ptr(), libc::O_WRONLY);
+        if fd < 0
+        {
+            return false;
+        }
+        let written = libc::write(fd, content.as_ptr() as *const libc::c_void, content.len());
+        libc::close(fd);
+        written == content.len() as isize
+    }
+}
+
+//struct mount_attr from linux/mount.h
+#[repr(C)]
+struct MountAttr
+{
+    attr_set: u64,
+    attr_clr: u64,
+    propagation: u64,
+    userns_fd: u64,
+}
+const MOUNT_ATTR_RDONLY: u64 = 0x1;
+
+//make every mount read-only, falls back to the root mount only on old kernels
+fn readonly_root() -> bool
+{
+    unsafe {
+        let root = c"/".as_ptr();
+        let attr = MountAttr { attr_set: MOUNT_ATTR_RDONLY, attr_clr: 0, propagation: 0, userns_fd: 0 };
+        if libc::syscall(libc::SYS_mount_setattr, libc::AT_FDCWD, root, libc::AT_RECURSIVE, &attr as *const MountAttr, std::mem::size_of::<MountAttr>()) == 0
+        {
+            return true;
+        }
+        libc::mount(root, root, std::ptr::null(), libc::MS_BIND | libc::MS_REC, std::ptr::null()) == 0
+            && libc::mount(std::ptr::null(), root, std::ptr::null(), libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY, std::ptr::null()) == 0
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+//syscalls a single-threaded program needs for computing and standard io
+const ALLOWED_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_read, libc::SYS_write, libc::SYS_readv, libc::SYS_writev,
+    libc::SYS_pread64, libc::SYS_pwrite64, libc::SYS_lseek, libc::SYS_close,
+    libc::SYS_fstat, libc::SYS_newfstatat, libc::SYS_statx, libc::SYS_fcntl,
+    libc::SYS_openat, libc::SYS_faccessat, libc::SYS_faccessat2, libc::SYS_readlinkat,
+    libc::SYS_ioctl, libc::SYS_dup, libc::SYS_dup3, libc::SYS_getcwd,
+    libc::SYS_mmap, libc::SYS_munmap, libc::SYS_mprotect, libc::SYS_mremap,
+    libc::SYS_brk, libc::SYS_madvise,
+    libc::SYS_rt_sigaction, libc::SYS_rt_sigprocmask, libc::SYS_rt_sigreturn, libc::SYS_sigaltstack,
+    libc::SYS_exit, libc::SYS_exit_group, libc::SYS_set_tid_address, libc::SYS_set_robust_list,
+    libc::SYS_rseq, libc::SYS_prlimit64, libc::SYS_getrandom, libc::SYS_futex,
+    libc::SYS_clock_gettime, libc::SYS_clock_getres, libc::SYS_clock_nanosleep, libc::SYS_nanosleep,
+    libc::SYS_gettimeofday, libc::SYS_getrusage, libc::SYS_times, libc::SYS_sysinfo,
+    libc::SYS_uname, libc::SYS_getpid, libc::SYS_gettid, libc::SYS_tgkill,
+    libc::SYS_getuid, libc::SYS_geteuid, libc::SYS_getgid, libc::SYS_getegid,
+    libc::SYS_sched_getaffinity, libc::SYS_sched_yield, libc::SYS_ppoll, libc::SYS_pselect6,
+    libc::SYS_execve,
+    #[cfg(target_arch = "x86_64")] libc::SYS_open,
+    #[cfg(target_arch = "x86_64")] libc::SYS_stat,
+    #[cfg(target_arch = "x86_64")] libc::SYS_lstat,
+    #[cfg(target_arch = "x86_64")] libc::SYS_access,
+    #[cfg(target_arch = "x86_64")] libc::SYS_readlink,
+    #[cfg(target_arch = "x86_64")] libc::SYS_arch_prctl,
+    #[cfg(target_arch = "x86_64")] libc::SYS_poll,
+    #[cfg(target_arch = "x86_64")] libc::SYS_select,
+    #[cfg(target_arch = "x86_64")] libc::SYS_dup2,
+    #[cfg(target_arch = "x86_64")] libc::SYS_time,
+];
+
+fn bpf_statement(code: u32, k: u32) -> libc::sock_filter
+{
+    libc::sock_filter { code: code as u16, jt: 0, jf: 0, k }
+}
+
+fn bpf_jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter
+{
+    libc::sock_filter { code: code as u16, jt, jf, k }
+}
+
+//seccomp allowlist
+//execve is needed to start the program and can not be limited to it, seccomp only
+//sees the path as a pointer the program could forge. it gains nothing though:
+//the filter and no_new_privs stay in force for whatever is executed
+fn seccomp_filter() -> Vec<libc::sock_filter>
+{
+    let load = libc::BPF_LD | libc::BPF_W | libc::BPF_ABS;
+    let jump = libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K;
+    let ret = libc::BPF_RET | libc::BPF_K;
+    //offsets in struct seccomp_data
+    let (nr, arch) = (0, 4);
+
+    let mut filter = vec![
+        bpf_statement(load, arch),
+        bpf_jump(jump, AUDIT_ARCH, 1, 0),
+        bpf_statement(ret, libc::SECCOMP_RET_KILL_PROCESS),
+        bpf_statement(load, nr),
+    ];
+    for syscall in ALLOWED_SYSCALLS.iter()
+    {
+        filter.push(bpf_jump(jump, *syscall as u32, 0, 1));
+        filter.push(bpf_statement(ret, libc::SECCOMP_RET_ALLOW));
+    }
+    filter.push(bpf_statement(ret, libc::SECCOMP_RET_KILL_PROCESS));
+    filter
+}
@@ -0,0 +1,216 @@
+use std::sync::{Mutex, MutexGuard};
+
+use crate::arg::Problem;
+use crate::api::contest::Contest;
+use crate::api::job::Job;
+use crate::api::rejudge::{Rejudge, RejudgeJob};
+use crate::api::state::JobState;
+use crate::api::user::User;
+use super::{JobFilter, JobPage, Result, Storage};
+
+#[derive(Default)]
+struct Tables
+{
+    jobs: Vec<Job>,
+    users: Vec<User>,
+    contests: Vec<Contest>,
+    problems: Vec<Problem>,
+    rejudges: Vec<Rejudge>,
+    next_job_id: usize,
+}
+
+//keeps nothing across restarts, for tests and throwaway judges
+#[derive(Default)]
+pub struct MemoryStorage
+{
+    tables: Mutex<Tables>,
+}
+
+impl MemoryStorage
+{
+    fn tables(&self) -> MutexGuard<'_, Tables>
+    {
+        self.tables.lock().unwrap()
+    }
+}
+
+//replace the row with the same id, like an sql UPDATE it does nothing if there is none
+fn update<T: Clone>(rows: &mut [T], row: &T, id: impl Fn(&T) -> usize)
+{
+    if let Some(old) = rows.iter_mut().find(|x| id(x) == id(row))
+    {
+        *old = row.clone();
+    }
+}
+
+impl Storage for MemoryStorage
+{
+    fn clear(&self) -> Result<()>
+    {
+        *self.tables() = Tables::default();
+        Ok(())
+    }
+
+    fn prepare(&self) -> Result<()>
+    {
+        Ok(())
+    }
+
+    fn query_jobs(&self, filter: &JobFilter, page: &JobPage) -> Result<Vec<Job>>
+    {
+        let tables = self.tables();
+        let jobs = tables.jobs.iter().filter(|x| filter.matches(x)).cloned().collect();
+        let after = page.after_id.and_then(|id| tables.jobs.iter().find(|x| x.id == id));
+        Ok(page.apply(jobs, after))
+    }
+
+    fn count_jobs(&self, filter: &JobFilter) -> Result<usize>
+    {
+        Ok(self.tables().jobs.iter().filter(|x| filter.matches(x)).count())
+    }
+
+    fn get_job(&self, job_id: usize) -> Result<Option<Job>>
+    {
+        Ok(self.tables().jobs.iter().find(|x| x.id == job_id).cloned())
+    }
+
+    fn next_job_id(&self) -> Result<usize>
+    {
+        Ok(self.tables().next_job_id)
+    }
+
+    fn read_users(&self) -> Result<Vec<User>>
+    {
+        Ok(self.tables().users.clone())
+    }
+
+    fn read_contests(&self) -> Result<Vec<Contest>>
+    {
+        Ok(self.tables().contests.clone())
+    }
+
+    fn read_problems(&self) -> Result<Vec<Problem>>
+    {
+        let mut problems = self.tables().problems.clone();
+        problems.sort_by_key(|x| x.id);
+        Ok(problems)
+    }
+
+    fn insert_job(&self, job: &Job) -> Result<()>
+    {
+        let mut tables = self.tables();
+        tables.next_job_id = tables.next_job_id.max(job.id + 1);
+        tables.jobs.push(job.clone());
+        Ok(())
+    }
+
+    fn update_job(&self, job: &Job) -> Result<()>
+    {
+        let mut tables = self.tables();
+        update(&mut tables.jobs, job, |x| x.id);
+        if job.state == JobState::Finished
+        {
+            let open = tables.rejudges.iter_mut()
+                .flat_map(|x| x.jobs.iter_mut())
+                .filter(|x| x.job_id == job.id && x.new_result.is_none());
+            for rejudge_job in open
+            {
+                rejudge_job.new_result = Some(job.result);
+                rejudge_job.new_score = Some(job.score);
+            }
+        }
+        Ok(())
+    }
+
+    fn delete_job(&self, job_id: usize) -> Result<()>
+    {
+        self.tables().jobs.retain(|x| x.id != job_id);
+        Ok(())
+    }
+
+    fn insert_rejudge(&self, rejudge_id: usize, created_time: &str, jobs: &[Job]) -> Result<Rejudge>
+    {
+        let mut tables = self.tables();
+        let mut rejudge = Rejudge {
+            id: rejudge_id,
+            created_time: created_time.to_string(),
+            jobs: Vec::new(),
+        };
+        for job in jobs
+        {
+            if let Some(old) = tables.jobs.iter_mut().find(|x| x.id == job.id && x.state == JobState::Finished)
+            {
+                rejudge.jobs.push(RejudgeJob {
+                    job_id: job.id,
+                    old_result: old.result,
+                    old_score: old.score,
+                    new_result: None,
+                    new_score: None,
+                });
+                //the submission is never changed, the job may have been read without its source
+                *old = Job { submission: old.submission.clone(), ..job.clone() };
+            }
+        }
+        tables.rejudges.push(rejudge.clone());
+        Ok(rejudge)
+    }
+
+    fn get_rejudge(&self, rejudge_id: usize) -> Result<Option<Rejudge>>
+    {
+        Ok(self.tables().rejudges.iter().find(|x| x.id == rejudge_id).cloned())
+    }
+
+    fn next_rejudge_id(&self) -> Result<usize>
+    {
+        Ok(self.tables().rejudges.iter().map(|x| x.id + 1).max().unwrap_or(0))
+    }
+
+    fn rejudge_of(&self, job_id: usize) -> Result<Option<usize>>
+    {
+        Ok(self.tables().rejudges.iter()
+            .find(|x| x.jobs.iter().any(|y| y.job_id == job_id && y.new_result.is_none()))
+            .map(|x| x.id))
+    }
+
+    fn insert_user(&self, user: &User) -> Result<()>
+    {
+        self.tables().users.push(user.clone());
+        Ok(())
+    }
+
+    fn update_user(&self, user: &User) -> Result<()>
+    {
+        update(&mut self.tables().users, user, |x| x.id);
+        Ok(())
+    }
+
+    fn insert_contest(&self, contest: &Contest) -> Result<()>
+    {
+        self.tables().contests.push(contest.clone());
+        Ok(())
+    }
+
+    fn update_contest(&self, contest: &Contest) -> Result<()>
+    {
+        update(&mut self.tables().contests, contest, |x| x.id);
+        Ok(())
+    }
+
+    fn insert_problem(&self, problem: &Problem) -> Result<()>
+    {
+        self.tables().problems.push(problem.clone());
+        Ok(())
+    }
+
+    fn update_problem(&self, problem: &Problem) -> Result<()>
+    {
+        update(&mut self.tables().problems, problem, |x| x.id);
+        Ok(())
+    }
+
+    fn delete_problem(&self, problem_id: usize) -> Result<()>
+    {
+        self.tables().problems.retain(|x| x.id != problem_id);
+        Ok(())
+    }
+}
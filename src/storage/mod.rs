@@ -0,0 +1,369 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::arg::{Problem, Server};
+use crate::globals::{CONTEST_LIST, PROBLEM_LIST, STORAGE, USER_LIST};
+use crate::api::contest::Contest;
+use crate::api::job::Job;
+use crate::api::rejudge::Rejudge;
+use crate::api::state::{JobResult, JobState};
+use crate::api::user::{Role, User};
+
+mod memory;
+mod sqlite;
+
+pub use memory::MemoryStorage;
+pub use sqlite::SqliteStorage;
+
+#[derive(Debug)]
+pub struct StorageError(String);
+
+impl fmt::Display for StorageError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        f.write_str(&self.0)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, StorageError>;
+
+//what GET /jobs asks for, None matches everything
+#[derive(Debug, Default, Clone)]
+pub struct JobFilter
+{
+    pub user_id: Option<usize>,
+    pub contest_id: Option<usize>,
+    pub problem_id: Option<usize>,
+    pub language: Option<String>,
+    //bounds on created_time, both inclusive
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub state: Option<JobState>,
+    pub result: Option<JobResult>,
+    //any of these users and problems, for the ranklists
+    pub user_ids: Option<Vec<usize>>,
+    pub problem_ids: Option<Vec<usize>>,
+}
+
+impl JobFilter
+{
+    pub fn matches(&self, job: &Job) -> bool
+    {
+        self.user_id.is_none_or(|x| job.submission.user_id == x)
+            && self.contest_id.is_none_or(|x| job.submission.contest_id == x)
+            && self.problem_id.is_none_or(|x| job.submission.problem_id == x)
+            && self.language.as_ref().is_none_or(|x| job.submission.language == *x)
+            && self.from.as_ref().is_none_or(|x| job.created_time >= *x)
+            && self.to.as_ref().is_none_or(|x| job.created_time <= *x)
+            && self.state.is_none_or(|x| job.state == x)
+            && self.result.is_none_or(|x| job.result == x)
+            && self.user_ids.as_ref().is_none_or(|x| x.contains(&job.submission.user_id))
+            && self.problem_ids.as_ref().is_none_or(|x| x.contains(&job.submission.problem_id))
+    }
+}
+
+//what jobs are ordered by, ties go by id in the same direction
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum JobSort
+{
+    #[default]
+    Id,
+    CreatedTime,
+    Score,
+}
+
+impl JobSort
+{
+    pub fn parse(sort: &str) -> Option<JobSort>
+    {
+        match sort
+        {
+            "id" => Some(JobSort::Id),
+            "created_time" => Some(JobSort::CreatedTime),
+            "score" => Some(JobSort::Score),
+            _ => None,
+        }
+    }
+
+    pub fn as_column(&self) -> &'static str
+    {
+        match self
+        {
+            JobSort::Id => "id",
+            JobSort::CreatedTime => "created_time",
+            JobSort::Score => "score",
+        }
+    }
+
+    fn compare(&self, a: &Job, b: &Job) -> Ordering
+    {
+        let order = match self
+        {
+            JobSort::Id => Ordering::Equal,
+            JobSort::CreatedTime => a.created_time.cmp(&b.created_time),
+            JobSort::Score => a.score.total_cmp(&b.score),
+        };
+        order.then(a.id.cmp(&b.id))
+    }
+}
+
+//which part of the matching jobs to return, all of them by id by default
+#[derive(Debug, Default, Clone)]
+pub struct JobPage
+{
+    pub sort: JobSort,
+    pub descending: bool,
+    //start right after this job in the sort order, it has to exist
+    pub after_id: Option<usize>,
+    pub offset: usize,
+    pub limit: Option<usize>,
+    //leave the source code of the submissions empty, it is not even read
+    pub skip_source: bool,
+}
+
+impl JobPage
+{
+    //sort and cut jobs that all match the filter, for storages without queries
+    //like the sql row values, after_id starts after where its job sorts even if
+    //that job does not match the filter, and nothing comes after a missing job
+    pub fn apply(&self, mut jobs: Vec<Job>, after: Option<&Job>) -> Vec<Job>
+    {
+        jobs.sort_by(|a, b| self.sort.compare(a, b));
+        if self.descending
+        {
+            jobs.reverse();
+        }
+        if self.after_id.is_some()
+        {
+            let beyond = if self.descending { Ordering::Less } else { Ordering::Greater };
+            jobs.retain(|x| after.is_some_and(|after| self.sort.compare(x, after) == beyond));
+        }
+        jobs.into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .map(|mut x| {
+                if self.skip_source
+                {
+                    x.submission.source_code.clear();
+                }
+                x
+            })
+            .collect()
+    }
+}
+
+//where jobs, users, contests and problems are kept
+//the handlers work on the lists in globals and write every change through here,
+//jobs and rejudges are only read from here, see cache::JobCache
+//update_job of a finished job also fills in its new result in open rejudges
+pub trait Storage: Send + Sync
+{
+    //drop all data, for --flush-data
+    fn clear(&self) -> Result<()>;
+    //create what is missing and bring old data up to date
+    fn prepare(&self) -> Result<()>;
+
+    //the page of jobs matching the filter
+    fn query_jobs(&self, filter: &JobFilter, page: &JobPage) -> Result<Vec<Job>>;
+    fn count_jobs(&self, filter: &JobFilter) -> Result<usize>;
+    fn get_job(&self, job_id: usize) -> Result<Option<Job>>;
+    //one more than the highest job id ever stored, ids of deleted jobs are not given out again
+    fn next_job_id(&self) -> Result<usize>;
+    fn read_users(&self) -> Result<Vec<User>>;
+    fn read_contests(&self) -> Result<Vec<Contest>>;
+    fn read_problems(&self) -> Result<Vec<Problem>>;
+
+    fn insert_job(&self, job: &Job) -> Result<()>;
+    fn update_job(&self, job: &Job) -> Result<()>;
+    fn delete_job(&self, job_id: usize) -> Result<()>;
+    //store jobs reset for judging again as rejudge `rejudge_id`, all in one go
+    //jobs no longer finished are left out, the rejudge has the old results of the others
+    fn insert_rejudge(&self, rejudge_id: usize, created_time: &str, jobs: &[Job]) -> Result<Rejudge>;
+    fn get_rejudge(&self, rejudge_id: usize) -> Result<Option<Rejudge>>;
+    fn next_rejudge_id(&self) -> Result<usize>;
+    //the open rejudge still waiting for this job, if any
+    fn rejudge_of(&self, job_id: usize) -> Result<Option<usize>>;
+    fn insert_user(&self, user: &User) -> Result<()>;
+    fn update_user(&self, user: &User) -> Result<()>;
+    fn insert_contest(&self, contest: &Contest) -> Result<()>;
+    fn update_contest(&self, contest: &Contest) -> Result<()>;
+    fn insert_problem(&self, problem: &Problem) -> Result<()>;
+    fn update_problem(&self, problem: &Problem) -> Result<()>;
+    fn delete_problem(&self, problem_id: usize) -> Result<()>;
+}
+
+//storage named by server.storage, "sqlite" at server.database or "memory"
+pub fn open(server: &Server) -> Result<Box<dyn Storage>>
+{
+    match server.storage.as_str()
+    {
+        "sqlite" => Ok(Box::new(SqliteStorage::open(&server.database)?)),
+        "memory" => Ok(Box::new(MemoryStorage::default())),
+        other => Err(StorageError(format!("Unknown storage {}", other))),
+    }
+}
+
+//the storage opened at startup
+pub fn storage() -> &'static dyn Storage
+{
+    STORAGE.get().expect("Storage is not open").as_ref()
+}
+
+//fill the lists in globals, with root as the first user
+pub async fn load_all() -> Result<()>
+{
+    let storage = storage();
+    *CONTEST_LIST.lock().await = storage.read_contests()?;
+    *PROBLEM_LIST.lock().await = storage.read_problems()?;
+
+    let mut users = storage.read_users()?;
+    if users.is_empty()
+    {
+        let root = User {
+            id: 0,
+            name: "root".to_string(),
+            role: Role::Admin,
+            password_hash: None,
+        };
+        storage.insert_user(&root)?;
+        users.push(root);
+    }
+    *USER_LIST.lock().await = users;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::api::job::PostJob;
+
+    //jobs of users 0 and 1 alternating, with scores going down
+    fn storages() -> Vec<Box<dyn Storage>>
+    {
+        let sqlite = SqliteStorage::open(":memory:").unwrap();
+        sqlite.prepare().unwrap();
+        let storages: Vec<Box<dyn Storage>> = vec![Box::new(sqlite), Box::new(MemoryStorage::default())];
+        for storage in &storages
+        {
+            for id in 0..6
+            {
+                let post_job = PostJob {
+                    source_code: format!("job {}", id),
+                    language: "Rust".to_string(),
+                    user_id: id % 2,
+                    contest_id: 0,
+                    problem_id: 0,
+                };
+                let mut job = Job::new(id, post_job, 1);
+                job.score = 100.0 - (id / 2) as f64 * 10.0;
+                storage.insert_job(&job).unwrap();
+            }
+        }
+        storages
+    }
+
+    fn ids(storage: &dyn Storage, filter: &JobFilter, page: &JobPage) -> Vec<usize>
+    {
+        storage.query_jobs(filter, page).unwrap().iter().map(|x| x.id).collect()
+    }
+
+    #[test]
+    fn cursor_outside_the_filter_pages_by_its_key()
+    {
+        let filter = JobFilter { user_id: Some(0), ..Default::default() };
+        for storage in storages()
+        {
+            //job 3 of user 1 scores 90 and sorts between jobs 2 (90) and 0 (100) of user 0
+            let page = JobPage { sort: JobSort::Score, after_id: Some(3), ..Default::default() };
+            assert_eq!(ids(storage.as_ref(), &filter, &page), [0]);
+            let page = JobPage { sort: JobSort::Score, descending: true, after_id: Some(3), ..Default::default() };
+            assert_eq!(ids(storage.as_ref(), &filter, &page), [2, 4]);
+            let page = JobPage { after_id: Some(1), limit: Some(1), ..Default::default() };
+            assert_eq!(ids(storage.as_ref(), &filter, &page), [2]);
+            let page = JobPage { after_id: Some(9), ..Default::default() };
+            assert_eq!(ids(storage.as_ref(), &filter, &page), Vec::<usize>::new());
+        }
+    }
+
+    #[test]
+    fn pages_follow_the_sort_order()
+    {
+        for storage in storages()
+        {
+            let page = JobPage { sort: JobSort::Score, descending: true, offset: 1, limit: Some(3), ..Default::default() };
+            assert_eq!(ids(storage.as_ref(), &JobFilter::default(), &page), [0, 3, 2]);
+            let page = JobPage { sort: JobSort::Score, limit: Some(2), ..Default::default() };
+            assert_eq!(ids(storage.as_ref(), &JobFilter::default(), &page), [4, 5]);
+            assert_eq!(storage.count_jobs(&JobFilter { user_id: Some(1), ..Default::default() }).unwrap(), 3);
+        }
+    }
+
+    #[test]
+    fn ranklist_filters_skip_the_source()
+    {
+        let filter = JobFilter { user_ids: Some(vec![1, 7]), problem_ids: Some(vec![0]), ..Default::default() };
+        for storage in storages()
+        {
+            let page = JobPage { skip_source: true, ..Default::default() };
+            let jobs = storage.query_jobs(&filter, &page).unwrap();
+            assert_eq!(jobs.iter().map(|x| x.id).collect::<Vec<usize>>(), [1, 3, 5]);
+            assert!(jobs.iter().all(|x| x.submission.source_code.is_empty()));
+            assert_eq!(storage.get_job(1).unwrap().unwrap().submission.source_code, "job 1");
+            let filter = JobFilter { problem_ids: Some(Vec::new()), ..Default::default() };
+            assert_eq!(storage.count_jobs(&filter).unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn deleted_job_ids_are_not_given_out_again()
+    {
+        for storage in storages()
+        {
+            assert_eq!(storage.next_job_id().unwrap(), 6);
+            storage.delete_job(5).unwrap();
+            storage.delete_job(4).unwrap();
+            assert_eq!(storage.next_job_id().unwrap(), 6);
+        }
+    }
+
+    #[test]
+    fn rejudges_requeue_finished_jobs_only()
+    {
+        for storage in storages()
+        {
+            let mut jobs = Vec::new();
+            for id in 0..3
+            {
+                let mut job = storage.get_job(id).unwrap().unwrap();
+                job.state = JobState::Finished;
+                job.result = JobResult::Accepted;
+                job.score = 100.0;
+                storage.update_job(&job).unwrap();
+                job.submission.source_code.clear();
+                jobs.push(Job::from(job, 2));
+            }
+            //job 3 was never finished
+            jobs.push(storage.get_job(3).unwrap().unwrap());
+
+            let rejudge = storage.insert_rejudge(0, "2022-08-27T02:05:29.000Z", &jobs).unwrap();
+            assert_eq!(rejudge.jobs.iter().map(|x| x.job_id).collect::<Vec<usize>>(), [0, 1, 2]);
+            assert!(rejudge.jobs.iter().all(|x| x.old_result == JobResult::Accepted && x.old_score == 100.0));
+            let mut job = storage.get_job(1).unwrap().unwrap();
+            assert_eq!(job.state, JobState::Queueing);
+            assert_eq!(job.cases.len(), 3);
+            assert_eq!(job.submission.source_code, "job 1");
+            assert_eq!(storage.rejudge_of(1).unwrap(), Some(0));
+            assert_eq!(storage.rejudge_of(3).unwrap(), None);
+
+            job.state = JobState::Finished;
+            job.result = JobResult::WrongAnswer;
+            storage.update_job(&job).unwrap();
+            assert_eq!(storage.rejudge_of(1).unwrap(), None);
+            let rejudge = storage.get_rejudge(0).unwrap().unwrap();
+            assert_eq!(rejudge.jobs[1].new_result, Some(JobResult::WrongAnswer));
+            assert_eq!(rejudge.jobs[0].new_result, None);
+        }
+    }
+}
@@ -0,0 +1,1031 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use rusqlite::{Connection, OptionalExtension, Row, ToSql, params, params_from_iter};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, Type, ValueRef};
+
+use crate::arg::Problem;
+use crate::api::contest::Contest;
+use crate::api::job::{Job, JobCase, PostJob};
+use crate::api::rejudge::{Rejudge, RejudgeJob};
+use crate::api::state::{JobResult, JobState};
+use crate::api::user::{Role, User};
+use super::{JobFilter, JobPage, JobSort, Result, Storage, StorageError};
+
+//one sqlite database file
+pub struct SqliteStorage
+{
+    database: Mutex<Connection>,
+}
+
+impl SqliteStorage
+{
+    pub fn open(path: &str) -> Result<SqliteStorage>
+    {
+        Ok(SqliteStorage {
+            database: Mutex::new(Connection::open(path)?),
+        })
+    }
+
+    fn database(&self) -> MutexGuard<'_, Connection>
+    {
+        self.database.lock().unwrap()
+    }
+}
+
+impl From<rusqlite::Error> for StorageError
+{
+    fn from(err: rusqlite::Error) -> StorageError
+    {
+        StorageError(err.to_string())
+    }
+}
+
+//job states and results are stored as their names
+macro_rules! sql_text
+{
+    ($name:ident) =>
+    {
+        impl ToSql for $name
+        {
+            fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>>
+            {
+                Ok(ToSqlOutput::from(self.as_str()))
+            }
+        }
+
+        impl FromSql for $name
+        {
+            fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self>
+            {
+                value.as_str()?.parse().map_err(|err: String| FromSqlError::Other(err.into()))
+            }
+        }
+    };
+}
+
+sql_text!(JobState);
+sql_text!(JobResult);
+
+//state and result only take the values of their enums
+fn jobs_table(name: &str) -> String
+{
+    format!(
+        "CREATE TABLE IF NOT EXISTS {} (
+            id INTEGER PRIMARY KEY,
+            created_time TEXT NOT NULL,
+            updated_time TEXT NOT NULL,
+            submission TEXT NOT NULL,
+            state TEXT NOT NULL CHECK (state IN ({})),
+            result TEXT NOT NULL CHECK (result IN ({})),
+            score REAL NOT NULL,
+            cases TEXT NOT NULL
+         )",
+        name,
+        sql_list(JobState::ALL.iter().map(|x| x.as_str())),
+        sql_list(JobResult::ALL.iter().map(|x| x.as_str())),
+    )
+}
+
+//a column holding json, which fails like any other column that does not convert
+fn json_column<T: serde::de::DeserializeOwned>(row: &Row, index: usize) -> rusqlite::Result<T>
+{
+    serde_json::from_str(row.get_ref(index)?.as_str()?)
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, err.into()))
+}
+
+fn sql_list<'a>(values: impl Iterator<Item = &'a str>) -> String
+{
+    values.map(|x| format!("'{}'", x)).collect::<Vec<String>>().join(", ")
+}
+
+//schema steps in order, the database keeps how many it has applied in schema_version
+//released steps must not change, add a new one instead
+type Migration = (&'static str, fn(&Connection) -> rusqlite::Result<()>);
+const MIGRATIONS: &[Migration] = &[
+    ("create tables", create_tables),
+    ("job states and results as enums", migrate_job_enums),
+    ("user roles and passwords", migrate_user_auth),
+    ("problem statements", migrate_problem_statements),
+    ("job columns and job_cases", migrate_job_columns),
+    ("rejudges", create_rejudges),
+    ("next job id", create_next_ids),
+    ("job state and created time indexes", create_job_indexes),
+];
+
+//the tables as of the first versioned schema
+//databases from before versioning may have older tables, the next steps fix those up
+fn create_tables(database: &Connection) -> rusqlite::Result<()>
+{
+    database.execute(&jobs_table("jobs"), [])?;
+
+    database.execute(
+        "CREATE TABLE IF NOT EXISTS users (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            role TEXT NOT NULL DEFAULT 'contestant',
+            password_hash TEXT
+         )",
+        [],
+    )?;
+
+    database.execute(
+        "CREATE TABLE IF NOT EXISTS contests (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            from_time TEXT NOT NULL,
+            to_time TEXT NOT NULL,
+            problem_ids TEXT NOT NULL,
+            user_ids TEXT NOT NULL,
+            submission_limit INTEGER NOT NULL
+         )",
+        [],
+    )?;
+
+    database.execute(
+        "CREATE TABLE IF NOT EXISTS problems (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            type TEXT NOT NULL,
+            misc TEXT NOT NULL,
+            statement TEXT NOT NULL DEFAULT '',
+            input_format TEXT NOT NULL DEFAULT '',
+            output_format TEXT NOT NULL DEFAULT '',
+            cases TEXT NOT NULL
+         )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+//jobs tables from before the enums have no CHECK constraints, rebuild them
+//with unknown states finished and unknown results turned into System Error
+fn migrate_job_enums(database: &Connection) -> rusqlite::Result<()>
+{
+    let sql: String = database.query_row(
+        "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'jobs'",
+        [],
+        |row| row.get(0),
+    )?;
+    if sql.contains("CHECK")
+    {
+        return Ok(());
+    }
+    database.execute_batch(&format!(
+        "DROP TABLE IF EXISTS jobs_new;
+        {};
+        INSERT INTO jobs_new SELECT id, created_time, updated_time, submission,
+            CASE WHEN state IN ({}) THEN state ELSE 'Finished' END,
+            CASE WHEN result IN ({}) THEN result ELSE 'System Error' END,
+            score, cases FROM jobs;
+        DROP TABLE jobs;
+        ALTER TABLE jobs_new RENAME TO jobs;",
+        jobs_table("jobs_new"),
+        sql_list(JobState::ALL.iter().map(|x| x.as_str())),
+        sql_list(JobResult::ALL.iter().map(|x| x.as_str())),
+    ))
+}
+
+//users tables from before authentication lack role and password_hash,
+//root keeps all rights there
+fn migrate_user_auth(database: &Connection) -> rusqlite::Result<()>
+{
+    let columns: Vec<String> = database.prepare("SELECT name FROM pragma_table_info('users')")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    if columns.iter().any(|x| x == "role")
+    {
+        return Ok(());
+    }
+    database.execute_batch(
+        "ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'contestant';
+        ALTER TABLE users ADD COLUMN password_hash TEXT;
+        UPDATE users SET role = 'admin' WHERE id = 0;")
+}
+
+//problems tables from before statements lack their text columns
+fn migrate_problem_statements(database: &Connection) -> rusqlite::Result<()>
+{
+    let columns: Vec<String> = database.prepare("SELECT name FROM pragma_table_info('problems')")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    if columns.iter().any(|x| x == "statement")
+    {
+        return Ok(());
+    }
+    database.execute_batch(
+        "ALTER TABLE problems ADD COLUMN statement TEXT NOT NULL DEFAULT '';
+        ALTER TABLE problems ADD COLUMN input_format TEXT NOT NULL DEFAULT '';
+        ALTER TABLE problems ADD COLUMN output_format TEXT NOT NULL DEFAULT '';")
+}
+
+//jobs kept the submission and cases as json, which can not be searched
+//split them into indexed columns and one job_cases row per case
+fn migrate_job_columns(database: &Connection) -> rusqlite::Result<()>
+{
+    let states = sql_list(JobState::ALL.iter().map(|x| x.as_str()));
+    let results = sql_list(JobResult::ALL.iter().map(|x| x.as_str()));
+    database.execute_batch(&format!(
+        "CREATE TABLE jobs_new (
+            id INTEGER PRIMARY KEY,
+            created_time TEXT NOT NULL,
+            updated_time TEXT NOT NULL,
+            user_id INTEGER NOT NULL,
+            contest_id INTEGER NOT NULL,
+            problem_id INTEGER NOT NULL,
+            language TEXT NOT NULL,
+            source_code TEXT NOT NULL,
+            state TEXT NOT NULL CHECK (state IN ({states})),
+            result TEXT NOT NULL CHECK (result IN ({results})),
+            score REAL NOT NULL
+         );
+        CREATE TABLE job_cases (
+            job_id INTEGER NOT NULL,
+            id INTEGER NOT NULL,
+            result TEXT NOT NULL CHECK (result IN ({results})),
+            time INTEGER NOT NULL,
+            cpu_time INTEGER NOT NULL,
+            memory INTEGER NOT NULL,
+            info TEXT NOT NULL,
+            PRIMARY KEY (job_id, id)
+         );"
+    ))?;
+
+    let jobs: Vec<(usize, String, String)> = database.prepare("SELECT id, submission, cases FROM jobs")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    //a job that does not parse fails the migration, which is rolled back
+    let unreadable = |id: usize, column: usize, err: serde_json::Error| {
+        rusqlite::Error::FromSqlConversionFailure(column, Type::Text, format!("job {}: {}", id, err).into())
+    };
+    for (id, submission, cases) in jobs
+    {
+        let submission: PostJob = serde_json::from_str(&submission).map_err(|err| unreadable(id, 1, err))?;
+        let cases: Vec<JobCase> = serde_json::from_str(&cases).map_err(|err| unreadable(id, 2, err))?;
+        database.execute(
+            "INSERT INTO jobs_new SELECT id, created_time, updated_time, ?1, ?2, ?3, ?4, ?5, state, result, score FROM jobs WHERE id = ?6",
+            params![
+                submission.user_id,
+                submission.contest_id,
+                submission.problem_id,
+                submission.language,
+                submission.source_code,
+                id,
+            ]
+        )?;
+        insert_job_cases(database, id, &cases)?;
+    }
+
+    database.execute_batch(
+        "DROP TABLE jobs;
+        ALTER TABLE jobs_new RENAME TO jobs;
+        CREATE INDEX jobs_user_id ON jobs (user_id);
+        CREATE INDEX jobs_contest_id ON jobs (contest_id);
+        CREATE INDEX jobs_problem_id ON jobs (problem_id);
+        CREATE INDEX jobs_language ON jobs (language);")
+}
+
+//batch rejudges and the results of their jobs before and after
+fn create_rejudges(database: &Connection) -> rusqlite::Result<()>
+{
+    let results = sql_list(JobResult::ALL.iter().map(|x| x.as_str()));
+    database.execute_batch(&format!(
+        "CREATE TABLE rejudges (
+            id INTEGER PRIMARY KEY,
+            created_time TEXT NOT NULL
+         );
+        CREATE TABLE rejudge_jobs (
+            rejudge_id INTEGER NOT NULL,
+            job_id INTEGER NOT NULL,
+            old_result TEXT NOT NULL CHECK (old_result IN ({results})),
+            old_score REAL NOT NULL,
+            new_result TEXT CHECK (new_result IN ({results})),
+            new_score REAL,
+            PRIMARY KEY (rejudge_id, job_id)
+         );
+        CREATE INDEX rejudge_jobs_job_id ON rejudge_jobs (job_id);"
+    ))
+}
+
+//the next id to give out, kept apart from the rows so that deleting the newest job
+//does not hand its id to the next submission
+fn create_next_ids(database: &Connection) -> rusqlite::Result<()>
+{
+    database.execute_batch(
+        "CREATE TABLE next_ids (
+            name TEXT PRIMARY KEY,
+            id INTEGER NOT NULL
+         );
+        INSERT INTO next_ids SELECT 'jobs', COALESCE(MAX(id) + 1, 0) FROM jobs;")
+}
+
+//GET /jobs filters on state and sorts and filters on created_time
+fn create_job_indexes(database: &Connection) -> rusqlite::Result<()>
+{
+    database.execute_batch(
+        "CREATE INDEX jobs_state ON jobs (state);
+        CREATE INDEX jobs_created_time ON jobs (created_time);")
+}
+
+//write all cases of a job, replacing the ones it had
+fn insert_job_cases(database: &Connection, job_id: usize, cases: &[JobCase]) -> rusqlite::Result<()>
+{
+    let mut statement = database.prepare_cached(
+        "INSERT OR REPLACE INTO job_cases (job_id, id, result, time, cpu_time, memory, info) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")?;
+    for case in cases
+    {
+        statement.execute(params![job_id, case.id, case.result, case.time, case.cpu_time, case.memory, case.info])?;
+    }
+    Ok(())
+}
+
+//write the changing columns and the cases of an existing job, false if there is none
+fn update_job_row(database: &Connection, job: &Job) -> rusqlite::Result<bool>
+{
+    let changed = database.execute(
+        "UPDATE jobs SET updated_time = ?1, state = ?2, result = ?3, score = ?4 WHERE id = ?5",
+        params![
+            job.updated_time,
+            job.state,
+            job.result,
+            job.score,
+            job.id,
+        ]
+    )?;
+    //a deleted job keeps no cases
+    if changed == 0
+    {
+        return Ok(false);
+    }
+    insert_job_cases(database, job.id, &job.cases)?;
+    //rejudged after its problem lost cases
+    database.execute(
+        "DELETE FROM job_cases WHERE job_id = ?1 AND id >= ?2",
+        params![job.id, job.cases.len()],
+    )?;
+    Ok(true)
+}
+
+//the WHERE clause for a job filter and its parameters, in order
+fn job_filter_sql(filter: &JobFilter) -> (String, Vec<Box<dyn ToSql + '_>>)
+{
+    let mut conditions: Vec<String> = Vec::new();
+    let mut values: Vec<Box<dyn ToSql + '_>> = Vec::new();
+    if let Some(user_id) = filter.user_id
+    {
+        conditions.push("user_id = ?".to_string());
+        values.push(Box::new(user_id));
+    }
+    if let Some(contest_id) = filter.contest_id
+    {
+        conditions.push("contest_id = ?".to_string());
+        values.push(Box::new(contest_id));
+    }
+    if let Some(problem_id) = filter.problem_id
+    {
+        conditions.push("problem_id = ?".to_string());
+        values.push(Box::new(problem_id));
+    }
+    if let Some(language) = &filter.language
+    {
+        conditions.push("language = ?".to_string());
+        values.push(Box::new(language));
+    }
+    if let Some(from) = &filter.from
+    {
+        conditions.push("created_time >= ?".to_string());
+        values.push(Box::new(from));
+    }
+    if let Some(to) = &filter.to
+    {
+        conditions.push("created_time <= ?".to_string());
+        values.push(Box::new(to));
+    }
+    if let Some(state) = filter.state
+    {
+        conditions.push("state = ?".to_string());
+        values.push(Box::new(state));
+    }
+    if let Some(result) = filter.result
+    {
+        conditions.push("result = ?".to_string());
+        values.push(Box::new(result));
+    }
+    for (column, ids) in [("user_id", &filter.user_ids), ("problem_id", &filter.problem_ids)]
+    {
+        if let Some(ids) = ids
+        {
+            conditions.push(format!("{} IN ({})", column, vec!["?"; ids.len()].join(", ")));
+            values.extend(ids.iter().map(|x| Box::new(*x) as Box<dyn ToSql>));
+        }
+    }
+    if conditions.is_empty()
+    {
+        return (String::new(), values);
+    }
+    (" WHERE ".to_string() + &conditions.join(" AND "), values)
+}
+
+//the job_filter_sql clause followed by the order, cursor and limit of a page
+fn job_page_sql<'a>(filter: &'a JobFilter, page: &JobPage) -> (String, Vec<Box<dyn ToSql + 'a>>)
+{
+    let (mut clause, mut values) = job_filter_sql(filter);
+    let column = page.sort.as_column();
+    let (compare, direction) = if page.descending { ("<", "DESC") } else { (">", "ASC") };
+    if let Some(after_id) = page.after_id
+    {
+        clause += if clause.is_empty() { " WHERE " } else { " AND " };
+        if page.sort == JobSort::Id
+        {
+            clause += &format!("id {} ?", compare);
+        }
+        else
+        {
+            clause += &format!("({0}, id) {1} (SELECT {0}, id FROM jobs WHERE id = ?)", column, compare);
+        }
+        values.push(Box::new(after_id));
+    }
+    if page.sort == JobSort::Id
+    {
+        clause += &format!(" ORDER BY id {}", direction);
+    }
+    else
+    {
+        clause += &format!(" ORDER BY {0} {1}, id {1}", column, direction);
+    }
+    //a negative limit is none
+    clause += " LIMIT ? OFFSET ?";
+    values.push(Box::new(page.limit.map_or(-1, |x| x as i64)));
+    values.push(Box::new(page.offset));
+    (clause, values)
+}
+
+//jobs with their cases, in the order of the clause which comes after FROM jobs
+fn select_jobs(database: &Connection, clause: &str, values: &[Box<dyn ToSql + '_>], skip_source: bool) -> rusqlite::Result<Vec<Job>>
+{
+    let mut cases: HashMap<usize, Vec<JobCase>> = HashMap::new();
+    let rows: Vec<(usize, JobCase)> = database.prepare(&format!(
+        "SELECT job_id, id, result, time, cpu_time, memory, info FROM job_cases WHERE job_id IN (SELECT id FROM jobs{}) ORDER BY job_id, id", clause))?
+        .query_map(params_from_iter(values.iter()), |row| {
+            Ok((row.get(0)?, JobCase {
+                id: row.get(1)?,
+                result: row.get(2)?,
+                time: row.get(3)?,
+                cpu_time: row.get(4)?,
+                memory: row.get(5)?,
+                info: row.get(6)?,
+                }))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    for (job_id, case) in rows
+    {
+        cases.entry(job_id).or_default().push(case);
+    }
+
+    let source_code = if skip_source { "''" } else { "source_code" };
+    database.prepare(&format!(
+        "SELECT id, created_time, updated_time, {}, language, user_id, contest_id, problem_id, state, result, score FROM jobs{}", source_code, clause))?
+        .query_map(params_from_iter(values.iter()), |row| {
+            let id: usize = row.get(0)?;
+            Ok(Job {
+                id,
+                created_time: row.get(1)?,
+                updated_time: row.get(2)?,
+                submission: PostJob {
+                    source_code: row.get(3)?,
+                    language: row.get(4)?,
+                    user_id: row.get(5)?,
+                    contest_id: row.get(6)?,
+                    problem_id: row.get(7)?,
+                },
+                state: row.get(8)?,
+                result: row.get(9)?,
+                score: row.get(10)?,
+                cases: cases.remove(&id).unwrap_or_default(),
+                })
+        })?
+        .collect()
+}
+
+//the number of migrations applied, 0 for new databases and those from before versioning
+fn schema_version(database: &Connection) -> rusqlite::Result<usize>
+{
+    database.execute("CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL)", [])?;
+    let version: Option<usize> = database.query_row("SELECT version FROM schema_version", [], |row| row.get(0)).optional()?;
+    match version
+    {
+        Some(version) => Ok(version),
+        None =>
+        {
+            database.execute("INSERT INTO schema_version (version) VALUES (0)", [])?;
+            Ok(0)
+        }
+    }
+}
+
+impl Storage for SqliteStorage
+{
+    fn clear(&self) -> Result<()>
+    {
+        let database = self.database();
+
+        let table_names: Vec<String> = database.prepare("SELECT name FROM sqlite_master WHERE type='table' AND name NOT LIKE 'sqlite_%';")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+
+        for table_name in table_names
+        {
+            let drop_sql = format!("DROP TABLE IF EXISTS {}", table_name);
+            database.execute(&drop_sql, [])?;
+        }
+
+        Ok(())
+    }
+
+    //run the migrations the database has not seen yet, each in its own transaction
+    fn prepare(&self) -> Result<()>
+    {
+        let mut database = self.database();
+        let version = schema_version(&database)?;
+        if version > MIGRATIONS.len()
+        {
+            return Err(StorageError(format!("Database schema version {} is newer than this server's {}", version, MIGRATIONS.len())));
+        }
+        for (index, (description, migrate)) in MIGRATIONS.iter().enumerate().skip(version)
+        {
+            let transaction = database.transaction()?;
+            migrate(&transaction)?;
+            transaction.execute("UPDATE schema_version SET version = ?1", params![index + 1])?;
+            transaction.commit()?;
+            log::info!("Database migrated to version {}: {}", index + 1, description);
+        }
+        Ok(())
+    }
+
+    fn query_jobs(&self, filter: &JobFilter, page: &JobPage) -> Result<Vec<Job>>
+    {
+        let (clause, values) = job_page_sql(filter, page);
+        Ok(select_jobs(&self.database(), &clause, &values, page.skip_source)?)
+    }
+
+    fn count_jobs(&self, filter: &JobFilter) -> Result<usize>
+    {
+        let (condition, values) = job_filter_sql(filter);
+        let count = self.database().query_row(
+            &format!("SELECT COUNT(*) FROM jobs{}", condition),
+            params_from_iter(values.iter()),
+            |row| row.get(0),
+        )?;
+        Ok(count)
+    }
+
+    fn get_job(&self, job_id: usize) -> Result<Option<Job>>
+    {
+        let values: Vec<Box<dyn ToSql>> = vec![Box::new(job_id)];
+        Ok(select_jobs(&self.database(), " WHERE id = ?", &values, false)?.pop())
+    }
+
+    fn next_job_id(&self) -> Result<usize>
+    {
+        let id = self.database().query_row("SELECT id FROM next_ids WHERE name = 'jobs'", [], |row| row.get(0))?;
+        Ok(id)
+    }
+
+    fn read_contests(&self) -> Result<Vec<Contest>>
+    {
+        let database = self.database();
+        let contests: rusqlite::Result<Vec<Contest>> = database.prepare(
+            "SELECT id, name, from_time, to_time, problem_ids, user_ids, submission_limit FROM contests")?
+            .query_map([], |row| {
+                Ok(Contest {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    from: row.get(2)?,
+                    to: row.get(3)?,
+                    problem_ids: json_column(row, 4)?,
+                    user_ids: json_column(row, 5)?,
+                    submission_limit: row.get(6)?,
+                    })
+            })?
+            .collect();
+
+        Ok(contests?)
+    }
+
+    fn read_problems(&self) -> Result<Vec<Problem>>
+    {
+        let database = self.database();
+        let problems: rusqlite::Result<Vec<Problem>> = database.prepare(
+            "SELECT id, name, type, misc, statement, input_format, output_format, cases FROM problems ORDER BY id")?
+            .query_map([], |row| {
+                Ok(Problem {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    problem_type: row.get(2)?,
+                    misc: json_column(row, 3)?,
+                    statement: row.get(4)?,
+                    input_format: row.get(5)?,
+                    output_format: row.get(6)?,
+                    cases: json_column(row, 7)?,
+                    })
+            })?
+            .collect();
+
+        Ok(problems?)
+    }
+
+    fn read_users(&self) -> Result<Vec<User>>
+    {
+        let database = self.database();
+        let users: rusqlite::Result<Vec<User>> = database.prepare(
+            "SELECT id, name, role, password_hash FROM users")?
+            .query_map([], |row| {
+                Ok(User {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    role: Role::parse(&row.get::<_, String>(2)?),
+                    password_hash: row.get(3)?,
+                    })
+            })?
+            .collect();
+
+        Ok(users?)
+    }
+
+    fn delete_job(&self, job_id: usize) -> Result<()>
+    {
+        let mut database = self.database();
+        let transaction = database.transaction()?;
+        transaction.execute("DELETE FROM job_cases WHERE job_id = ?1", params![job_id])?;
+        transaction.execute("DELETE FROM jobs WHERE id = ?1", params![job_id])?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn update_job(&self, job: &Job) -> Result<()>
+    {
+        let mut database = self.database();
+        let transaction = database.transaction()?;
+        update_job_row(&transaction, job)?;
+        if job.state == JobState::Finished
+        {
+            transaction.execute(
+                "UPDATE rejudge_jobs SET new_result = ?1, new_score = ?2 WHERE job_id = ?3 AND new_result IS NULL",
+                params![job.result, job.score, job.id],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn insert_job(&self, job: &Job) -> Result<()>
+    {
+        let mut database = self.database();
+        let transaction = database.transaction()?;
+        transaction.execute(
+            "INSERT INTO jobs (id, created_time, updated_time, user_id, contest_id, problem_id, language, source_code, state, result, score)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                job.id,
+                job.created_time,
+                job.updated_time,
+                job.submission.user_id,
+                job.submission.contest_id,
+                job.submission.problem_id,
+                job.submission.language,
+                job.submission.source_code,
+                job.state,
+                job.result,
+                job.score,
+            ]
+        )?;
+        insert_job_cases(&transaction, job.id, &job.cases)?;
+        transaction.execute("UPDATE next_ids SET id = MAX(id, ?1) WHERE name = 'jobs'", params![job.id + 1])?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn insert_rejudge(&self, rejudge_id: usize, created_time: &str, jobs: &[Job]) -> Result<Rejudge>
+    {
+        let mut database = self.database();
+        let transaction = database.transaction()?;
+        transaction.execute(
+            "INSERT INTO rejudges (id, created_time) VALUES (?1, ?2)",
+            params![rejudge_id, created_time],
+        )?;
+        let mut rejudge = Rejudge {
+            id: rejudge_id,
+            created_time: created_time.to_string(),
+            jobs: Vec::new(),
+        };
+        for job in jobs
+        {
+            let old: Option<(JobResult, f64)> = transaction.query_row(
+                "SELECT result, score FROM jobs WHERE id = ?1 AND state = ?2",
+                params![job.id, JobState::Finished],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            ).optional()?;
+            let (old_result, old_score) = match old
+            {
+                Some(old) => old,
+                None => continue,
+            };
+            transaction.execute(
+                "INSERT INTO rejudge_jobs (rejudge_id, job_id, old_result, old_score) VALUES (?1, ?2, ?3, ?4)",
+                params![rejudge_id, job.id, old_result, old_score],
+            )?;
+            update_job_row(&transaction, job)?;
+            rejudge.jobs.push(RejudgeJob {
+                job_id: job.id,
+                old_result,
+                old_score,
+                new_result: None,
+                new_score: None,
+            });
+        }
+        transaction.commit()?;
+        Ok(rejudge)
+    }
+
+    fn get_rejudge(&self, rejudge_id: usize) -> Result<Option<Rejudge>>
+    {
+        let database = self.database();
+        let created_time: Option<String> = database.query_row(
+            "SELECT created_time FROM rejudges WHERE id = ?1",
+            params![rejudge_id],
+            |row| row.get(0),
+        ).optional()?;
+        let created_time = match created_time
+        {
+            Some(created_time) => created_time,
+            None => return Ok(None),
+        };
+        let jobs: rusqlite::Result<Vec<RejudgeJob>> = database.prepare(
+            "SELECT job_id, old_result, old_score, new_result, new_score FROM rejudge_jobs WHERE rejudge_id = ?1 ORDER BY job_id")?
+            .query_map(params![rejudge_id], |row| {
+                Ok(RejudgeJob {
+                    job_id: row.get(0)?,
+                    old_result: row.get(1)?,
+                    old_score: row.get(2)?,
+                    new_result: row.get(3)?,
+                    new_score: row.get(4)?,
+                    })
+            })?
+            .collect();
+
+        Ok(Some(Rejudge {
+            id: rejudge_id,
+            created_time,
+            jobs: jobs?,
+        }))
+    }
+
+    fn next_rejudge_id(&self) -> Result<usize>
+    {
+        let id = self.database().query_row("SELECT COALESCE(MAX(id) + 1, 0) FROM rejudges", [], |row| row.get(0))?;
+        Ok(id)
+    }
+
+    fn rejudge_of(&self, job_id: usize) -> Result<Option<usize>>
+    {
+        let id = self.database().query_row(
+            "SELECT rejudge_id FROM rejudge_jobs WHERE job_id = ?1 AND new_result IS NULL",
+            params![job_id],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(id)
+    }
+
+    fn update_user(&self, user: &User) -> Result<()>
+    {
+        let database = self.database();
+        database.execute(
+            "UPDATE users SET name = ?1, role = ?2, password_hash = ?3 WHERE id = ?4",
+            params![
+                user.name,
+                user.role.as_str(),
+                user.password_hash,
+                user.id,
+            ]
+        )?;
+        Ok(())
+    }
+
+    fn insert_user(&self, user: &User) -> Result<()>
+    {
+        let database = self.database();
+        database.execute(
+            "INSERT INTO users (id, name, role, password_hash) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                user.id,
+                user.name,
+                user.role.as_str(),
+                user.password_hash,
+            ]
+        )?;
+        Ok(())
+    }
+
+    fn update_contest(&self, contest: &Contest) -> Result<()>
+    {
+        let database = self.database();
+        database.execute(
+            "UPDATE contests SET name = ?1, from_time = ?2, to_time = ?3, problem_ids = ?4, user_ids = ?5, submission_limit = ?6 WHERE id = ?7",
+            params![
+                contest.name,
+                contest.from,
+                contest.to,
+                serde_json::to_string(&contest.problem_ids).unwrap(),
+                serde_json::to_string(&contest.user_ids).unwrap(),
+                contest.submission_limit,
+                contest.id,
+            ]
+        )?;
+        Ok(())
+    }
+
+    fn insert_contest(&self, contest: &Contest) -> Result<()>
+    {
+        let database = self.database();
+        database.execute(
+            "INSERT INTO contests (id, name, from_time, to_time, problem_ids, user_ids, submission_limit) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                contest.id,
+                contest.name,
+                contest.from,
+                contest.to,
+                serde_json::to_string(&contest.problem_ids).unwrap(),
+                serde_json::to_string(&contest.user_ids).unwrap(),
+                contest.submission_limit,
+            ]
+        )?;
+        Ok(())
+    }
+
+    fn update_problem(&self, problem: &Problem) -> Result<()>
+    {
+        let database = self.database();
+        database.execute(
+            "UPDATE problems SET name = ?1, type = ?2, misc = ?3, statement = ?4, input_format = ?5, output_format = ?6, cases = ?7 WHERE id = ?8",
+            params![
+                problem.name,
+                problem.problem_type,
+                serde_json::to_string(&problem.misc).unwrap(),
+                problem.statement,
+                problem.input_format,
+                problem.output_format,
+                serde_json::to_string(&problem.cases).unwrap(),
+                problem.id,
+            ]
+        )?;
+        Ok(())
+    }
+
+    fn insert_problem(&self, problem: &Problem) -> Result<()>
+    {
+        let database = self.database();
+        database.execute(
+            "INSERT INTO problems (id, name, type, misc, statement, input_format, output_format, cases) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                problem.id,
+                problem.name,
+                problem.problem_type,
+                serde_json::to_string(&problem.misc).unwrap(),
+                problem.statement,
+                problem.input_format,
+                problem.output_format,
+                serde_json::to_string(&problem.cases).unwrap(),
+            ]
+        )?;
+        Ok(())
+    }
+
+    fn delete_problem(&self, problem_id: usize) -> Result<()>
+    {
+        let database = self.database();
+        database.execute(
+            "DELETE FROM problems WHERE id = ?1",
+            params![problem_id],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    //the schema and rows written by the server before versioning
+    fn baseline_storage(cases: &str) -> SqliteStorage
+    {
+        let storage = SqliteStorage::open(":memory:").unwrap();
+        storage.database().execute_batch(&format!(
+            "CREATE TABLE jobs (
+                id INTEGER PRIMARY KEY,
+                created_time TEXT NOT NULL,
+                updated_time TEXT NOT NULL,
+                submission TEXT NOT NULL,
+                state TEXT NOT NULL,
+                result TEXT NOT NULL,
+                score REAL NOT NULL,
+                cases TEXT NOT NULL
+             );
+            CREATE TABLE users (
+                id INTEGER PRIMARY KEY,
+                name TEXT NOT NULL
+             );
+            CREATE TABLE contests (
+                id INTEGER PRIMARY KEY,
+                name TEXT NOT NULL,
+                from_time TEXT NOT NULL,
+                to_time TEXT NOT NULL,
+                problem_ids TEXT NOT NULL,
+                user_ids TEXT NOT NULL,
+                submission_limit INTEGER NOT NULL
+             );
+            INSERT INTO users VALUES (0, 'root'), (1, 'alice');
+            INSERT INTO jobs VALUES (3, '2022-08-27T02:05:29.000Z', '2022-08-27T02:05:30.000Z',
+                '{{\"source_code\":\"fn main() {{}}\",\"language\":\"Rust\",\"user_id\":1,\"contest_id\":0,\"problem_id\":2}}',
+                'Finished', 'Wrong Answer', 50.0, '{}');"
+            , cases.replace('\'', "''"))).unwrap();
+        storage
+    }
+
+    fn columns(storage: &SqliteStorage, table: &str) -> Vec<String>
+    {
+        storage.database().prepare(&format!("SELECT name FROM pragma_table_info('{}')", table)).unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<rusqlite::Result<Vec<String>>>().unwrap()
+    }
+
+    #[test]
+    fn baseline_database_is_migrated()
+    {
+        let storage = baseline_storage(
+            r#"[{"id":0,"result":"Compilation Success","time":0,"memory":0,"info":""},
+                {"id":1,"result":"Accepted","time":1500,"memory":2048,"info":""},
+                {"id":2,"result":"Wrong Answer","time":1700,"memory":2048,"info":""}]"#);
+        storage.prepare().unwrap();
+
+        assert_eq!(schema_version(&storage.database()).unwrap(), MIGRATIONS.len());
+        assert_eq!(columns(&storage, "jobs"), [
+            "id", "created_time", "updated_time", "user_id", "contest_id", "problem_id",
+            "language", "source_code", "state", "result", "score",
+        ]);
+        assert!(columns(&storage, "users").contains(&"role".to_string()));
+        let indexes: Vec<String> = storage.database()
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'index' AND tbl_name = 'jobs' AND sql IS NOT NULL ORDER BY name").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<rusqlite::Result<_>>().unwrap();
+        assert_eq!(indexes, [
+            "jobs_contest_id", "jobs_created_time", "jobs_language", "jobs_problem_id", "jobs_state", "jobs_user_id",
+        ]);
+        let rows: Vec<(usize, String, u64, u64, u64)> = storage.database()
+            .prepare("SELECT id, result, time, cpu_time, memory FROM job_cases WHERE job_id = 3 ORDER BY id").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))).unwrap()
+            .collect::<rusqlite::Result<_>>().unwrap();
+        assert_eq!(rows, [
+            (0, "Compilation Success".to_string(), 0, 0, 0),
+            (1, "Accepted".to_string(), 1500, 0, 2048),
+            (2, "Wrong Answer".to_string(), 1700, 0, 2048),
+        ]);
+
+        let job = storage.get_job(3).unwrap().unwrap();
+        assert_eq!(job.submission.user_id, 1);
+        assert_eq!(job.submission.problem_id, 2);
+        assert_eq!(job.submission.source_code, "fn main() {}");
+        assert_eq!(job.result, JobResult::WrongAnswer);
+        assert_eq!(job.cases.len(), 3);
+        assert_eq!(storage.next_job_id().unwrap(), 4);
+        let users = storage.read_users().unwrap();
+        assert_eq!(users[0].role, Role::Admin);
+        assert_eq!(users[1].role, Role::Contestant);
+    }
+
+    #[test]
+    fn unreadable_job_rolls_back_its_migration()
+    {
+        let storage = baseline_storage("not json");
+        assert!(storage.prepare().is_err());
+
+        //the steps before it stay applied, the jobs keep their json
+        assert_eq!(schema_version(&storage.database()).unwrap(), 4);
+        assert!(columns(&storage, "jobs").contains(&"cases".to_string()));
+        let tables: usize = storage.database()
+            .query_row("SELECT COUNT(*) FROM sqlite_master WHERE name IN ('jobs_new', 'job_cases')", [], |row| row.get(0)).unwrap();
+        assert_eq!(tables, 0);
+    }
+
+    #[test]
+    fn unreadable_json_columns_are_errors()
+    {
+        let storage = baseline_storage("[]");
+        storage.prepare().unwrap();
+        storage.database().execute_batch(
+            "INSERT INTO contests VALUES (1, 'broken', '2022-08-27T02:05:29.000Z', '2022-08-28T02:05:29.000Z', '[0', '[0]', 1);
+            INSERT INTO problems (id, name, type, misc, cases) VALUES (0, 'broken', 'standard', '{}', 'not json');").unwrap();
+        assert!(storage.read_contests().is_err());
+        assert!(storage.read_problems().is_err());
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+use crate::arg::{Config, Problem};
+
+//problem types the judge knows, others are compared strictly
+const PROBLEM_TYPES: [&str; 4] = ["standard", "strict", "spj", "dynamic_ranking"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity
+{
+    //the server refuses to start with it
+    Error,
+    //works, but probably not as meant
+    Warning,
+}
+
+//one finding in a config, located by its json path
+#[derive(Debug, Clone)]
+pub struct Diagnostic
+{
+    pub severity: Severity,
+    //the included file the path is in, None for the config file itself
+    pub file: Option<String>,
+    pub path: String,
+    pub message: String,
+}
+
+impl Diagnostic
+{
+    pub fn location(&self) -> String
+    {
+        match &self.file
+        {
+            Some(file) => file.clone() + ": " + &self.path,
+            None => self.path.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        let severity = match self.severity
+        {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}: {}", severity, self.location(), self.message)
+    }
+}
+
+struct Checker
+{
+    diagnostics: Vec<Diagnostic>,
+    //the file of the problem being checked
+    file: Option<String>,
+}
+
+impl Checker
+{
+    fn error(&mut self, path: String, message: String)
+    {
+        self.push(Severity::Error, path, message);
+    }
+
+    fn warning(&mut self, path: String, message: String)
+    {
+        self.push(Severity::Warning, path, message);
+    }
+
+    fn push(&mut self, severity: Severity, path: String, message: String)
+    {
+        //a file with a single problem has it at the root
+        let path = path.trim_start_matches('.').to_string();
+        self.diagnostics.push(Diagnostic { severity, file: self.file.clone(), path, message });
+    }
+}
+
+//everything that would only fail later while judging, in config order
+pub fn check(config: &Config) -> Vec<Diagnostic>
+{
+    let mut checker = Checker { diagnostics: Vec::new(), file: None };
+    check_server(&mut checker, config);
+
+    let mut problem_ids: HashSet<usize> = HashSet::new();
+    for (index, problem) in config.problems.iter().enumerate()
+    {
+        //paths point into the file the problem was written in, not the expanded list
+        let path = match config.problem_sources.get(index)
+        {
+            Some(source) =>
+            {
+                checker.file = source.file.clone();
+                source.path.clone()
+            }
+            None => format!("problems[{}]", index),
+        };
+        if !problem_ids.insert(problem.id)
+        {
+            checker.error(path.clone() + ".id", format!("duplicate problem id {}", problem.id));
+        }
+        check_problem(&mut checker, &path, problem);
+    }
+    checker.file = None;
+
+    let mut language_names: HashSet<&str> = HashSet::new();
+    for (index, language) in config.languages.iter().enumerate()
+    {
+        let path = format!("languages[{}]", index);
+        if !language_names.insert(&language.name)
+        {
+            checker.error(path.clone() + ".name", format!("duplicate language {}", language.name));
+        }
+        if language.file_name.is_empty()
+        {
+            checker.error(path.clone() + ".file_name", "is empty".to_string());
+        }
+        if language.command.is_empty()
+        {
+            checker.error(path.clone() + ".command", "is empty".to_string());
+        }
+        for placeholder in ["%INPUT%", "%OUTPUT%"]
+        {
+            if !language.command.iter().skip(1).any(|x| x == placeholder)
+            {
+                checker.error(path.clone() + ".command", format!("has no {} argument", placeholder));
+            }
+        }
+    }
+
+    checker.diagnostics
+}
+
+fn check_server(checker: &mut Checker, config: &Config)
+{
+    let server = &config.server;
+    if !server.wall_time_ratio.is_finite() || server.wall_time_ratio <= 0.0
+    {
+        checker.error("server.wall_time_ratio".to_string(), "must be a positive number".to_string());
+    }
+    else if server.wall_time_ratio < 1.0
+    {
+        checker.warning("server.wall_time_ratio".to_string(), "below 1.0 cuts cases off before their time limit".to_string());
+    }
+    if server.storage != "sqlite" && server.storage != "memory"
+    {
+        checker.error("server.storage".to_string(), format!("unknown storage {}, use sqlite or memory", server.storage));
+    }
+    if server.judge_workers == 0
+    {
+        checker.warning("server.judge_workers".to_string(), "is 0, one worker is used".to_string());
+    }
+    if let Some(auth) = &server.auth
+    {
+        if auth.secret.is_empty()
+        {
+            checker.error("server.auth.secret".to_string(), "is empty".to_string());
+        }
+    }
+}
+
+//a problem sent to the api, its case files may still be uploaded later
+pub fn check_posted_problem(problem: &Problem) -> Vec<Diagnostic>
+{
+    let mut checker = Checker { diagnostics: Vec::new(), file: None };
+    check_definition(&mut checker, "", problem);
+    checker.diagnostics
+}
+
+fn check_problem(checker: &mut Checker, path: &str, problem: &Problem)
+{
+    for (index, case) in problem.cases.iter().enumerate()
+    {
+        for (field, file) in [("input_file", &case.input_file), ("answer_file", &case.answer_file)]
+        {
+            if !Path::new(file).is_file()
+            {
+                checker.error(format!("{}.cases[{}].{}", path, index, field), format!("{} does not exist", file));
+            }
+        }
+    }
+    check_definition(checker, path, problem);
+}
+
+fn check_definition(checker: &mut Checker, path: &str, problem: &Problem)
+{
+    if !PROBLEM_TYPES.contains(&problem.problem_type.as_str())
+    {
+        checker.warning(path.to_string() + ".type", format!("unknown type {}, output is compared strictly", problem.problem_type));
+    }
+    if problem.cases.is_empty()
+    {
+        checker.warning(path.to_string() + ".cases", "is empty".to_string());
+    }
+
+    match problem.problem_type.as_str()
+    {
+        "spj" =>
+        {
+            let command = problem.misc.get("special_judge").and_then(|x| x.as_array());
+            match command
+            {
+                Some(command) if !command.is_empty() && command.iter().all(|x| x.is_string()) => {}
+                Some(_) => checker.error(path.to_string() + ".misc.special_judge", "must be a non-empty list of strings".to_string()),
+                None => checker.error(path.to_string() + ".misc.special_judge", "is missing for an spj problem".to_string()),
+            }
+        }
+        "dynamic_ranking" =>
+        {
+            match problem.misc.get("dynamic_ranking_ratio").and_then(|x| x.as_f64())
+            {
+                Some(ratio) if (0.0..=1.0).contains(&ratio) => {}
+                Some(_) => checker.warning(path.to_string() + ".misc.dynamic_ranking_ratio", "is clamped to [0, 1]".to_string()),
+                None => checker.warning(path.to_string() + ".misc.dynamic_ranking_ratio", "is missing, scored as a standard problem".to_string()),
+            }
+        }
+        _ => {}
+    }
+
+    if let Some(packing) = problem.misc.get("packing")
+    {
+        let packs = match packing.as_array()
+        {
+            Some(packs) => packs,
+            None =>
+            {
+                checker.warning(path.to_string() + ".misc.packing", "is not a list, ignored".to_string());
+                return;
+            }
+        };
+        for (index, pack) in packs.iter().enumerate()
+        {
+            let pack_path = format!("{}.misc.packing[{}]", path, index);
+            match pack.as_array()
+            {
+                Some(cases) =>
+                {
+                    for case in cases.iter()
+                    {
+                        match case.as_u64()
+                        {
+                            Some(case) if case >= 1 && case as usize <= problem.cases.len() => {}
+                            _ => checker.warning(pack_path.clone(), format!("case {} does not exist, ignored", case)),
+                        }
+                    }
+                }
+                None => checker.warning(pack_path, "is not a list, ignored".to_string()),
+            }
+        }
+    }
+}
@@ -2,14 +2,14 @@ use assert_json_diff::{assert_json_matches_no_panic, CompareMode, Config};
 use lazy_static::lazy_static;
 use reqwest::blocking::{Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
-use serde_json;
 use serde_json::Value;
+use std::collections::HashMap;
 use std::env::consts::EXE_EXTENSION;
 use std::fs::{File, OpenOptions};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::{Child, Command, Stdio};
-use std::sync::Once;
+use std::sync::{Mutex, Once};
 use std::time::Duration;
 
 // The code was originally written by Jack O'Connor (@oconnor663)
@@ -42,7 +42,7 @@ fn build_and_find_path(name: &str) -> PathBuf {
     static CARGO_BUILD_ONCE: Once = Once::new();
     CARGO_BUILD_ONCE.call_once(|| {
         let mut build_command = Command::new("cargo");
-        build_command.args(&["build", "--quiet"]);
+        build_command.args(["build", "--quiet"]);
         if !cfg!(debug_assertions) {
             build_command.arg("--release");
         }
@@ -66,6 +66,8 @@ fn build_and_find_path(name: &str) -> PathBuf {
 lazy_static! {
     static ref EXE_PATH: PathBuf = build_and_find_path("oj");
     static ref CLIENT: Client = Client::new();
+    // all cases share one server port, so only one may run at a time
+    static ref SERVER_LOCK: Mutex<()> = Mutex::new(());
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -75,10 +77,18 @@ struct TestRequest {
     content: Value,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct WriteFile {
+    path: String,
+    content: Value,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 struct TestResponse {
     status: u16,
     content: Value,
+    #[serde(default)]
+    headers: HashMap<String, String>, // headers the response must have, with these values
 }
 
 fn _default_true() -> bool {
@@ -108,6 +118,12 @@ struct HttpComm {
     restart_server: bool, // restart server before sending request
     #[serde(default = "_default_false")]
     skip_body: bool, // do not check body
+    #[serde(default)]
+    as_user: Option<String>, // send the token that this user got from the last login
+    #[serde(default)]
+    token: Option<String>, // send this token as is
+    #[serde(default)]
+    write_file: Option<WriteFile>, // write this json file before sending the request
 }
 
 pub struct TestCase {
@@ -119,6 +135,8 @@ pub struct TestCase {
     stdout_file: PathBuf,
     stderr_file: PathBuf,
     http_file: PathBuf,
+    tokens: HashMap<String, String>, // the last token of each user name that logged in
+    pub storage: Option<String>,     // run the server on this storage instead of the configured one
 }
 
 impl TestCase {
@@ -151,6 +169,8 @@ impl TestCase {
             stdout_file,
             stderr_file,
             http_file,
+            tokens: HashMap::new(),
+            storage: None,
         }
     }
 
@@ -206,7 +226,7 @@ impl TestCase {
     fn start_server(&mut self, restart: bool) {
         // ensure no server is running
         CLIENT
-            .post(&format!("{}/internal/exit", self.prefix))
+            .post(format!("{}/internal/exit", self.prefix))
             .send()
             .ok();
         // sleep 1 second for server shutdown
@@ -227,19 +247,22 @@ impl TestCase {
             .open(&self.stderr_file)
             .expect("failed to create stderr file");
 
-        let command = Command::new(EXE_PATH.as_os_str())
+        let mut command = Command::new(EXE_PATH.as_os_str());
+        if let Some(storage) = &self.storage {
+            command.env("OJ_SERVER__STORAGE", storage);
+        }
+        let command = command
             .args(&self.arguments)
             .stdin(Stdio::piped())
             .stdout(Stdio::from(stdout))
             .stderr(Stdio::from(stderr))
             .spawn()
-            .expect(
-                format!(
+            .unwrap_or_else(|_| {
+                panic!(
                     "case {} incorrect: failed to execute server process",
                     self.name
                 )
-                .as_str(),
-            );
+            });
         self.running_process = Some(command);
         // sleep 1 second for server startup
         std::thread::sleep(Duration::from_secs(1));
@@ -264,9 +287,9 @@ impl TestCase {
 
     fn kill_server(&mut self) {
         if let Some(mut child) = self.running_process.take() {
-            child.kill().expect(
-                format!("case {} incorrect: cannot kill server process", self.name).as_str(),
-            );
+            child.kill().unwrap_or_else(|_| {
+                panic!("case {} incorrect: cannot kill server process", self.name)
+            });
         }
     }
 
@@ -285,14 +308,30 @@ impl TestCase {
             self.arguments = old_arguments;
         }
 
+        if let Some(file) = &c.write_file {
+            std::fs::write(&file.path, file.content.to_string()).unwrap_or_else(|_| {
+                panic!("case {} incorrect: cannot write {}", self.name, file.path)
+            });
+        }
+
         let url = format!("{}/{}", &self.prefix, &c.request.path);
         let method =
-            reqwest::Method::from_bytes(&c.request.method.to_uppercase().as_bytes()).unwrap();
+            reqwest::Method::from_bytes(c.request.method.to_uppercase().as_bytes()).unwrap();
+
+        let token = match &c.as_user {
+            Some(user) => Some(self.tokens.get(user).cloned().unwrap_or_else(|| {
+                panic!("case {} incorrect: {} has not logged in", self.name, user)
+            })),
+            None => c.token.clone(),
+        };
 
         let check_status_and_get_body = |url: &str, method: reqwest::Method| -> Value {
             let mut request = CLIENT
                 .request(method.clone(), url)
                 .timeout(Duration::from_millis(c.timeout));
+            if let Some(token) = &token {
+                request = request.bearer_auth(token);
+            }
             if let reqwest::Method::GET = method {
                 // no json body
             } else {
@@ -301,22 +340,30 @@ impl TestCase {
 
             let (mut resp, mut http_file) = self
                 .log_and_send(request)
-                .expect(format!("case {} incorrect: HTTP request failed", self.name).as_str());
+                .unwrap_or_else(|_| panic!("case {} incorrect: HTTP request failed", self.name));
 
             let code = resp.status().as_u16();
+            for (name, value) in &c.response.headers {
+                assert_eq!(
+                    resp.headers().get(name).and_then(|x| x.to_str().ok()),
+                    Some(value.as_str()),
+                    "case {} incorrect: wrong {} header",
+                    self.name,
+                    name
+                );
+            }
 
             let value = if c.skip_body {
                 resp.copy_to(&mut http_file).ok();
                 writeln!(http_file).ok();
                 Value::Null
             } else {
-                let json: Value = resp.json().expect(
-                    format!(
+                let json: Value = resp.json().unwrap_or_else(|_| {
+                    panic!(
                         "case {} incorrect: cannot decode response body as JSON, status code is {}",
                         self.name, code
                     )
-                    .as_str(),
-                );
+                });
 
                 serde_json::to_writer(&http_file, &json).ok();
                 writeln!(http_file).ok();
@@ -342,7 +389,7 @@ impl TestCase {
             if let Value::Number(id) = &body["id"] as &Value {
                 job_id = id
                     .as_u64()
-                    .expect(format!("case {} incorrect: job id is not valid", self.name).as_str());
+                    .unwrap_or_else(|| panic!("case {} incorrect: job id is not valid", self.name));
             } else {
                 panic!(
                     "case {} incorrect: cannot get job id after submission",
@@ -354,7 +401,7 @@ impl TestCase {
             let poll_url = format!("{}/jobs/{}", &self.prefix, job_id);
             for _ in 0..c.poll_count {
                 std::thread::sleep(Duration::from_secs(1));
-                body = check_status_and_get_body(&poll_url.as_str(), reqwest::Method::GET);
+                body = check_status_and_get_body(poll_url.as_str(), reqwest::Method::GET);
                 if job_finished(&body) {
                     break;
                 }
@@ -367,6 +414,11 @@ impl TestCase {
             }
         }
 
+        // remember tokens from logins
+        if let (Some(token), Some(user)) = (body["token"].as_str(), body["user"]["name"].as_str()) {
+            self.tokens.insert(user.to_string(), token.to_string());
+        }
+
         // check final result
         if let Err(error) = assert_json_matches_no_panic(
             &body,
@@ -382,6 +434,8 @@ impl TestCase {
     }
 
     pub fn run(&mut self) -> Vec<Value> {
+        // a panicking case leaves the lock poisoned, the next one restarts the server anyway
+        let _server = SERVER_LOCK.lock().unwrap_or_else(|err| err.into_inner());
         self.start_server(false);
         // send requests sequentially
         let res = self
@@ -0,0 +1,71 @@
+mod common;
+use common::TestCase;
+
+#[test]
+fn test_ext_01_authentication() {
+    // check authentication
+    // 1. log in and check that tokens are required, checked and expire
+    // 2. check the roles of the routes
+    // 3. check that jobs are submitted as the logged in user
+    // 4. check that contestants only see their own source code
+    // 5. check that a new password voids the old tokens
+    let results = TestCase::read("ext_01_authentication").run();
+    for result in [&results[14], &results[15].as_array().unwrap()[0]] {
+        assert!(
+            result["submission"].get("source_code").is_none(),
+            "case ext_01_authentication incorrect: source code of another contestant is shown"
+        );
+    }
+}
+
+#[test]
+fn test_ext_02_problem_edit() {
+    // rejudge a job after its problem got more and then fewer cases
+    let results = TestCase::read("ext_02_problem_edit").run();
+    assert_eq!(
+        results[4]["cases"].as_array().unwrap().len(),
+        2,
+        "case ext_02_problem_edit incorrect: cases of the old problem are left"
+    );
+}
+
+#[test]
+fn test_ext_03_config_reload() {
+    // rejudge a job after a config reload changed the cases of its problem
+    std::fs::copy(
+        "tests/cases/ext_03_config_reload.problems.json",
+        "target/ext_03_config_reload.problems.json",
+    )
+    .unwrap();
+    TestCase::read("ext_03_config_reload").run();
+}
+
+#[test]
+fn test_ext_04_job_pages() {
+    // check paging, sorting and counting GET /jobs
+    // 1. pages and the X-Total-Count header
+    // 2. sorting by score, with ties by id
+    // 3. the after_id cursor, also when its job does not match the filter
+    // 4. leaving out the source code
+    let results = TestCase::read("ext_04_job_pages").run();
+    for (index, length) in [(4, 2), (5, 2), (6, 4), (7, 2), (8, 1), (9, 1)] {
+        assert_eq!(
+            results[index].as_array().unwrap().len(),
+            length,
+            "case ext_04_job_pages incorrect: wrong page length"
+        );
+    }
+    assert!(
+        results[9][0]["submission"].get("source_code").is_none(),
+        "case ext_04_job_pages incorrect: source code is shown"
+    );
+}
+
+#[test]
+fn test_ext_05_batch_rejudge() {
+    // check batch rejudging
+    // 1. requeue all finished jobs of a problem with their old results
+    // 2. check that its queued jobs can not be deleted or canceled, with one worker job 2 waits
+    // 3. check that the batch finishes with the new results
+    TestCase::read("ext_05_batch_rejudge").run();
+}
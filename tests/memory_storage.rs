@@ -0,0 +1,58 @@
+mod common;
+use common::TestCase;
+
+// run a case on the memory storage, which has to answer like sqlite
+fn run_in_memory(name: &str) {
+    let mut case = TestCase::read(name);
+    case.storage = Some("memory".to_string());
+    case.run();
+}
+
+// every case but adv_02_persistent_storage, which restarts the server
+macro_rules! memory_cases {
+    ($($test:ident => $name:literal,)*) => {
+        $(
+            #[test]
+            fn $test() {
+                run_in_memory($name);
+            }
+        )*
+    };
+}
+
+memory_cases! {
+    test_memory_01_01_hello_world => "01_01_hello_world",
+    test_memory_01_02_wrong_results => "01_02_wrong_results",
+    test_memory_01_03_multiple_cases => "01_03_multiple_cases",
+    test_memory_01_04_time_limit_exceeded => "01_04_time_limit_exceeded",
+    test_memory_01_05_strict_compare => "01_05_strict_compare",
+    test_memory_02_01_cpp_support => "02_01_cpp_support",
+    test_memory_03_01_job_list => "03_01_job_list",
+    test_memory_03_02_job_list_with_filter => "03_02_job_list_with_filter",
+    test_memory_03_03_rejudging => "03_03_rejudging",
+    test_memory_04_01_user_support => "04_01_user_support",
+    test_memory_05_01_global_ranklist => "05_01_global_ranklist",
+    test_memory_05_02_scoring_rule => "05_02_scoring_rule",
+    test_memory_05_03_tie_breaker => "05_03_tie_breaker",
+    test_memory_adv_01_contest_support => "adv_01_contest_support",
+    test_memory_adv_03_nonblocking_judging => "adv_03_nonblocking_judging",
+    test_memory_adv_04_01_report_memory_usage => "adv_04_01_report_memory_usage",
+    test_memory_adv_04_02_limit_memory_usage => "adv_04_02_limit_memory_usage",
+    test_memory_adv_05_packed_judging => "adv_05_packed_judging",
+    test_memory_adv_06_special_judge => "adv_06_special_judge",
+    test_memory_adv_07_dynamic_ranking => "adv_07_dynamic_ranking",
+    test_memory_ext_01_authentication => "ext_01_authentication",
+    test_memory_ext_02_problem_edit => "ext_02_problem_edit",
+    test_memory_ext_04_job_pages => "ext_04_job_pages",
+    test_memory_ext_05_batch_rejudge => "ext_05_batch_rejudge",
+}
+
+#[test]
+fn test_memory_ext_03_config_reload() {
+    std::fs::copy(
+        "tests/cases/ext_03_config_reload.problems.json",
+        "target/ext_03_config_reload.problems.json",
+    )
+    .unwrap();
+    run_in_memory("ext_03_config_reload");
+}